@@ -0,0 +1,153 @@
+//! # Trolloc Macros
+//!
+//! The `#[trolloc::test]` attribute. Use it through `trolloc`, which re-exports it next to the
+//! [`harness`](../trolloc/harness/index.html) it expands to.
+//!
+//! There are no dependencies here, not even `syn`: all the attribute needs from the function is
+//! its name, and all it needs from its arguments is a handful of `key = value` pairs.
+
+use proc_macro::{Delimiter, Group, Ident, Literal, Punct, Spacing, Span, TokenStream, TokenTree};
+
+/// Run a test many times over, with a different trolling seed each time.
+///
+/// The test takes the [`Trollocator`](../trolloc/gjallocator/struct.Trollocator.html) to
+/// allocate from, fresh for every run:
+///
+/// ```ignore
+/// #[trolloc::test(seed = 42, runs = 100)]
+/// fn vec_survives(allocator: &Trollocator<0>) {
+///     // ...
+/// }
+/// ```
+///
+/// Only what is allocated from that allocator gets trolled. The global allocator is left alone,
+/// so a plain `Vec::new()` or `Box::new()` in the test is as safe as anywhere else. Allocate
+/// through the allocator by hand, or with the `nightly` feature, with `Vec::new_in(allocator)`
+/// and friends.
+///
+/// - `seed`: seed of the first run, the rest count up from it. Defaults to 0.
+/// - `runs`: how many runs, each in a process of its own. Defaults to 32.
+///
+/// Other attributes, like `#[should_panic]` or `#[ignore]`, apply to the test as a whole.
+#[proc_macro_attribute]
+pub fn test(attr: TokenStream, item: TokenStream) -> TokenStream {
+    match expand(attr, item) {
+        Ok(tokens) => tokens,
+        Err((message, span)) => compile_error(message, span),
+    }
+}
+
+/// A mistake in how the attribute was used, and where.
+type Error = (&'static str, Span);
+
+fn expand(attr: TokenStream, item: TokenStream) -> Result<TokenStream, Error> {
+    let (seed, runs) = parse_args(attr)?;
+
+    // Outer attributes go on the test, everything else is the trial function
+    let mut tokens = item.into_iter().peekable();
+    let mut attributes = Vec::new();
+    while let Some(TokenTree::Punct(punct)) = tokens.peek() {
+        if punct.as_char() != '#' {
+            break;
+        }
+
+        attributes.push(tokens.next().unwrap());
+        match tokens.next() {
+            Some(group @ TokenTree::Group(_)) => attributes.push(group),
+            _ => return Err(("expected an attribute", Span::call_site())),
+        }
+    }
+    let trial: Vec<TokenTree> = tokens.collect();
+    let name = function_name(&trial)?;
+
+    // #[test] fn name() { trial; ::trolloc::harness::run(Trial { .. }, name) }
+    let mut body: TokenStream = trial.into_iter().collect();
+    body.extend(parse(&format!(
+        "::trolloc::harness::run(::trolloc::harness::Trial {{ \
+            name: ::core::concat!(::core::module_path!(), \"::\", \"{name}\"), \
+            seed: {seed}, \
+            runs: {runs}, \
+        }}, {name});"
+    )));
+
+    let mut test: TokenStream = attributes.into_iter().collect();
+    // Spelled out, in case the caller glob imported `trolloc::test` over the built-in one
+    test.extend(parse("#[::core::prelude::v1::test]"));
+    test.extend([
+        TokenTree::Ident(Ident::new("fn", Span::call_site())),
+        TokenTree::Ident(name),
+        TokenTree::Group(Group::new(Delimiter::Parenthesis, TokenStream::new())),
+        TokenTree::Group(Group::new(Delimiter::Brace, body)),
+    ]);
+
+    Ok(test)
+}
+
+/// Parse `seed = .., runs = ..`, either of which can be left out.
+fn parse_args(attr: TokenStream) -> Result<(Literal, Literal), Error> {
+    let mut seed = Literal::u64_unsuffixed(0);
+    let mut runs = Literal::usize_unsuffixed(32);
+
+    let mut tokens = attr.into_iter();
+    while let Some(key) = tokens.next() {
+        let TokenTree::Ident(key) = key else {
+            return Err(("expected `seed` or `runs`", key.span()));
+        };
+
+        match tokens.next() {
+            Some(TokenTree::Punct(punct)) if punct.as_char() == '=' => {}
+            _ => return Err(("expected `=`", key.span())),
+        }
+
+        let value = match tokens.next() {
+            Some(TokenTree::Literal(value)) => value,
+            _ => return Err(("expected a number", key.span())),
+        };
+
+        match key.to_string().as_str() {
+            "seed" => seed = value,
+            "runs" => runs = value,
+            _ => return Err(("expected `seed` or `runs`", key.span())),
+        }
+
+        match tokens.next() {
+            None => break,
+            Some(TokenTree::Punct(punct)) if punct.as_char() == ',' => {}
+            Some(other) => return Err(("expected `,`", other.span())),
+        }
+    }
+
+    Ok((seed, runs))
+}
+
+/// Find the name of the function, which is whatever comes after `fn`.
+fn function_name(item: &[TokenTree]) -> Result<Ident, Error> {
+    item.windows(2)
+        .find_map(|pair| match pair {
+            [TokenTree::Ident(keyword), TokenTree::Ident(name)] if keyword.to_string() == "fn" => Some(name.clone()),
+            _ => None,
+        })
+        .ok_or(("#[trolloc::test] only works on functions", Span::call_site()))
+}
+
+/// Parse some Rust we wrote ourselves, so it had better parse.
+fn parse(source: &str) -> TokenStream {
+    source.parse().expect("generated code does not parse")
+}
+
+/// `compile_error!("message")`, pointing at `span`.
+fn compile_error(message: &str, span: Span) -> TokenStream {
+    let mut bang = Punct::new('!', Spacing::Alone);
+    bang.set_span(span);
+    let mut message = Literal::string(message);
+    message.set_span(span);
+
+    [
+        TokenTree::Ident(Ident::new("compile_error", span)),
+        TokenTree::Punct(bang),
+        TokenTree::Group(Group::new(Delimiter::Parenthesis, TokenTree::Literal(message).into())),
+        TokenTree::Punct(Punct::new(';', Spacing::Alone)),
+    ]
+    .into_iter()
+    .collect()
+}
@@ -0,0 +1,330 @@
+//! # Trolloc Preload
+//!
+//! The C `malloc` family over [`gjallocator::Trollocator`](trolloc::gjallocator::Trollocator),
+//! built as a shared library so that existing C and C++ programs can be trolled too:
+//!
+//! ```sh
+//! cargo build --release -p trolloc-preload --features trolling
+//! LD_PRELOAD=target/release/libtrolloc_preload.so ./some-program
+//! ```
+//!
+//! These live in their own crate because exporting `malloc` and friends with C linkage replaces
+//! them for *everything* linked into the same binary, which is exactly what we want from a
+//! preloaded library and exactly what we do not want from every Rust program using `trolloc`.
+//! Without the `trolling` feature, which has to be asked for so that building the whole workspace
+//! does not turn on trolling for everything else in it, the library is merely a bad `malloc`.
+//!
+//! How to troll is read from the [`env`](trolloc::policy::env) variables on the first call, which
+//! is easier to get right with the `trolloc` command line tool than by hand:
+//!
+//! - `TROLLOC_SEED`, `TROLLOC_PROBABILITY` and `TROLLOC_ACTIONS` make the
+//!   [`TrollPolicy`](trolloc::policy::TrollPolicy).
+//! - `TROLLOC_HEAP_SIZE` is the size of the heap in bytes, 64 MiB by default. It is mapped in
+//!   lazily, so a big one only costs what gets used.
+//! - `TROLLOC_LOG` is a file to append a line to for every troll: the action, the address and the
+//!   size of the victim, and whatever else there is to know, like which bit got flipped.
+//!
+//! The trollocator is not thread safe on its own, so every call here takes a spin lock first.
+//! Pointers that did not come from the trollocator heap (for example, ones the dynamic loader
+//! handed out before we were loaded) are quietly ignored by `free`.
+
+// Everything exported here has the same safety contract as its C counterpart.
+#![allow(clippy::missing_safety_doc)]
+
+use core::{
+    alloc::{GlobalAlloc, Layout},
+    ffi::{c_char, c_int, c_void, CStr},
+    fmt::{self, Write},
+    hint,
+    sync::atomic::{AtomicBool, AtomicI32, Ordering},
+};
+
+use trolloc::{
+    gjallocator::Trollocator,
+    policy::{env, TrollEvent, TrollPolicy},
+};
+
+/// Default heap size for preloaded programs, 64 MiB. C programs tend to be hungrier than our demos.
+const HEAP_SIZE: usize = 64 << 20;
+
+/// Alignment of plain `malloc` results, the same as glibc's on 64-bit targets.
+const MALLOC_ALIGNMENT: usize = 2 * core::mem::size_of::<usize>();
+
+const EINVAL: c_int = 22;
+const ENOMEM: c_int = 12;
+
+const PROT_READ: c_int = 0x1;
+const PROT_WRITE: c_int = 0x2;
+const MAP_PRIVATE: c_int = 0x02;
+const MAP_ANONYMOUS: c_int = 0x20;
+const MAP_NORESERVE: c_int = 0x4000;
+const MAP_FAILED: *mut c_void = !0 as *mut c_void;
+
+const O_WRONLY: c_int = 0o1;
+const O_CREAT: c_int = 0o100;
+const O_APPEND: c_int = 0o2000;
+const O_NOFOLLOW: c_int = 0o400000;
+const O_CLOEXEC: c_int = 0o2000000;
+
+const STDERR: c_int = 2;
+
+/// The heap lives wherever [`setup`] maps it, so there is no inline one.
+static ALLOCATOR: Trollocator<0> = Trollocator::new();
+
+/// Held while anything is touching [`ALLOCATOR`].
+static LOCK: AtomicBool = AtomicBool::new(false);
+
+/// Whether [`setup`] has run. Only touched with the lock held.
+static READY: AtomicBool = AtomicBool::new(false);
+
+/// File descriptor of the troll log, or -1 for none.
+static LOG_FD: AtomicI32 = AtomicI32::new(-1);
+
+extern "C" {
+    fn __errno_location() -> *mut c_int;
+    fn getenv(name: *const c_char) -> *const c_char;
+    fn mmap(addr: *mut c_void, len: usize, prot: c_int, flags: c_int, fd: c_int, offset: i64) -> *mut c_void;
+    fn open(path: *const c_char, flags: c_int, ...) -> c_int;
+    fn write(fd: c_int, buf: *const c_void, count: usize) -> isize;
+    fn abort() -> !;
+}
+
+/// Run `f` while holding the allocator lock.
+fn locked<R>(f: impl FnOnce(&Trollocator<0>) -> R) -> R {
+    while LOCK.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+        hint::spin_loop();
+    }
+
+    if !READY.load(Ordering::Relaxed) {
+        // SAFETY: We hold the lock and nothing has been allocated yet
+        unsafe { setup() };
+        READY.store(true, Ordering::Relaxed);
+    }
+
+    let result = f(&ALLOCATOR);
+    LOCK.store(false, Ordering::Release);
+    result
+}
+
+/// Look up an environment variable, without allocating. Empty ones count as missing.
+unsafe fn var(name: &str) -> Option<&'static str> {
+    // Long enough for every `TROLLOC_*` name, plus the nul
+    let mut c_name = [0u8; 32];
+    c_name[..name.len()].copy_from_slice(name.as_bytes());
+
+    let value = getenv(c_name.as_ptr().cast());
+    if value.is_null() {
+        return None;
+    }
+
+    CStr::from_ptr(value).to_str().ok().filter(|value| !value.is_empty())
+}
+
+/// Complain on stderr and bring the program down. We cannot troll it the way it asked.
+unsafe fn fail(message: &str) -> ! {
+    let mut line = LineBuffer::new();
+    let _ = writeln!(line, "trolloc: {message}");
+    line.write_to(STDERR);
+    abort()
+}
+
+/// Read the configuration from the environment, map in the heap and open the log.
+unsafe fn setup() {
+    let policy = match TrollPolicy::from_env(|name| var(name)) {
+        Ok(policy) => policy,
+        Err(message) => fail(message),
+    };
+
+    let heap_size = match var(env::HEAP_SIZE).map(str::parse::<usize>) {
+        None => HEAP_SIZE,
+        Some(Ok(heap_size)) => heap_size,
+        Some(Err(_)) => fail("heap size is not a number of bytes"),
+    };
+
+    let heap = mmap(
+        core::ptr::null_mut(),
+        heap_size,
+        PROT_READ | PROT_WRITE,
+        MAP_PRIVATE | MAP_ANONYMOUS | MAP_NORESERVE,
+        -1,
+        0,
+    );
+    if heap == MAP_FAILED || !ALLOCATOR.init(heap.cast(), heap_size) {
+        fail("could not set up the heap");
+    }
+
+    if let Some(path) = var(env::LOG) {
+        // `var` handed out a C string, so the nul is right after it. Not following a symlink
+        // someone planted there, and not leaking into whatever the program execs.
+        let fd = open(path.as_ptr().cast(), O_WRONLY | O_CREAT | O_APPEND | O_NOFOLLOW | O_CLOEXEC, 0o644 as c_int);
+        if fd < 0 {
+            fail("could not open the troll log");
+        }
+
+        LOG_FD.store(fd, Ordering::Relaxed);
+        ALLOCATOR.set_policy(TrollPolicy { log: Some(log), ..policy });
+    } else {
+        ALLOCATOR.set_policy(policy);
+    }
+}
+
+/// Append a troll to the log.
+fn log(event: &TrollEvent) {
+    let mut line = LineBuffer::new();
+    let _ = writeln!(line, "{event}");
+    line.write_to(LOG_FD.load(Ordering::Relaxed));
+}
+
+/// A line of text on the stack, since we are the ones who would have to allocate it.
+struct LineBuffer {
+    bytes: [u8; 128],
+    len: usize,
+}
+
+impl LineBuffer {
+    fn new() -> Self {
+        Self { bytes: [0; 128], len: 0 }
+    }
+
+    /// Write the line out to a file descriptor, all in one go so that lines do not get mixed up.
+    fn write_to(&self, fd: c_int) {
+        // SAFETY: The buffer is valid for `len` bytes
+        unsafe { write(fd, self.bytes.as_ptr().cast(), self.len) };
+    }
+}
+
+impl Write for LineBuffer {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > self.bytes.len() {
+            return Err(fmt::Error);
+        }
+
+        self.bytes[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Set `errno` to `ENOMEM` if an allocation failed, and pass it through either way.
+unsafe fn check_oom(ptr: *mut u8) -> *mut c_void {
+    if ptr.is_null() {
+        *__errno_location() = ENOMEM;
+    }
+
+    ptr.cast()
+}
+
+/// Check whether a pointer points into the trollocator heap.
+fn owns(allocator: &Trollocator<0>, ptr: *mut c_void) -> bool {
+    (allocator.heap_start()..allocator.heap_end()).contains(&(ptr as usize))
+}
+
+/// Allocate `size` bytes aligned to `align`, which must be a power of two.
+unsafe fn alloc_aligned(size: usize, align: usize) -> *mut c_void {
+    match Layout::from_size_align(size, align.max(MALLOC_ALIGNMENT)) {
+        Ok(layout) => check_oom(locked(|allocator| allocator.alloc(layout))),
+        Err(_) => check_oom(core::ptr::null_mut()),
+    }
+}
+
+/// Allocate `size` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn malloc(size: usize) -> *mut c_void {
+    alloc_aligned(size, MALLOC_ALIGNMENT)
+}
+
+/// Allocate `count * size` zeroed bytes.
+#[no_mangle]
+pub unsafe extern "C" fn calloc(count: usize, size: usize) -> *mut c_void {
+    let Some(total) = count.checked_mul(size) else {
+        return check_oom(core::ptr::null_mut());
+    };
+
+    match Layout::from_size_align(total, MALLOC_ALIGNMENT) {
+        Ok(layout) => check_oom(locked(|allocator| allocator.alloc_zeroed(layout))),
+        Err(_) => check_oom(core::ptr::null_mut()),
+    }
+}
+
+/// Resize a block to `size` bytes, moving it if necessary.
+#[no_mangle]
+pub unsafe extern "C" fn realloc(ptr: *mut c_void, size: usize) -> *mut c_void {
+    if ptr.is_null() {
+        return malloc(size);
+    }
+
+    if size == 0 {
+        free(ptr);
+        return core::ptr::null_mut();
+    }
+
+    locked(|allocator| {
+        if !owns(allocator, ptr) {
+            return check_oom(core::ptr::null_mut());
+        }
+
+        // The trollocator only needs the old size to know how much to copy
+        let layout = Layout::from_size_align_unchecked(allocator.usable_size(ptr.cast()), MALLOC_ALIGNMENT);
+        check_oom(allocator.realloc(ptr.cast(), layout, size))
+    })
+}
+
+/// Free a block.
+#[no_mangle]
+pub unsafe extern "C" fn free(ptr: *mut c_void) {
+    if ptr.is_null() {
+        return;
+    }
+
+    locked(|allocator| {
+        if owns(allocator, ptr) {
+            // The trollocator does not care about the layout
+            allocator.dealloc(ptr.cast(), Layout::from_size_align_unchecked(0, 1));
+        }
+    })
+}
+
+/// Allocate `size` bytes aligned to `align`, storing the block in `memptr`.
+#[no_mangle]
+pub unsafe extern "C" fn posix_memalign(memptr: *mut *mut c_void, align: usize, size: usize) -> c_int {
+    if !align.is_power_of_two() || !align.is_multiple_of(core::mem::size_of::<*mut c_void>()) {
+        return EINVAL;
+    }
+
+    let ptr = alloc_aligned(size, align);
+    if ptr.is_null() {
+        return ENOMEM;
+    }
+
+    *memptr = ptr;
+    0
+}
+
+/// Allocate `size` bytes aligned to `align`.
+#[no_mangle]
+pub unsafe extern "C" fn aligned_alloc(align: usize, size: usize) -> *mut c_void {
+    if !align.is_power_of_two() {
+        *__errno_location() = EINVAL;
+        return core::ptr::null_mut();
+    }
+
+    alloc_aligned(size, align)
+}
+
+/// Allocate `size` bytes aligned to `align`. Obsolete, but programs still call it, and its
+/// blocks get passed to our `free`.
+#[no_mangle]
+pub unsafe extern "C" fn memalign(align: usize, size: usize) -> *mut c_void {
+    aligned_alloc(align, size)
+}
+
+/// Get the number of bytes usable in a block, which can be more than was asked for.
+#[no_mangle]
+pub unsafe extern "C" fn malloc_usable_size(ptr: *mut c_void) -> usize {
+    if ptr.is_null() {
+        return 0;
+    }
+
+    locked(|allocator| if owns(allocator, ptr) { allocator.usable_size(ptr.cast()) } else { 0 })
+}
@@ -15,20 +15,15 @@
 
 #![deprecated]
 
-#[cfg(feature = "std")]
-extern crate core;
-
-#[cfg(feature = "alloc")]
-extern crate alloc;
-
 const HEADER_SIZE: usize = core::mem::size_of::<BlockHeader>();
 const MIN_BLOCK_SIZE: usize = core::mem::size_of::<Block>();
-const MAX_HEAP_SIZE: usize = 0x10000;
+/// Heap size of a [`Trollocator`] when none is given, 64 KiB.
+pub const DEFAULT_HEAP_SIZE: usize = 0x10000;
 pub(crate) const ALIGNMENT: usize = 8;
 
-use core::{alloc::{Layout}, mem::{align_of, self}, ptr::*, panic};
+use core::{alloc::Layout, mem};
 
-use crate::xorshift;
+use crate::{free_list::{FreeList, FreeNode, Linked}, xorshift};
 
 type BlockPointer = *mut Block;
 
@@ -43,55 +38,66 @@ struct BlockHeader {
     free: bool,
 }
 
-#[repr(C)]
-/// Free list linkage pointers, overlaps payload space.
-struct FreeNode {
-    /// Previous free block.
-    prev: BlockPointer,
-    /// Next free block.
-    next: BlockPointer,
-}
-
 #[repr(C)]
 /// Smallest unit of allocator.
 pub struct Block {
     /// Header data, including size and pointer to previous block start.
     header: BlockHeader,
     /// Pointers for free blocks.
-    free_node: FreeNode,
+    free_node: FreeNode<Block>,
+}
+
+unsafe impl Linked for Block {
+    unsafe fn links(node: *mut Self) -> *mut FreeNode<Self> {
+        core::ptr::addr_of_mut!((*node).free_node)
+    }
 }
 
 /// Core allocator struct.
 ///
 /// Represents an instance of the troll allocator. Supports all basic memory allocator functions.
-pub struct Trollocator {
+/// `N` is the size of the heap in bytes.
+pub struct Trollocator<const N: usize = DEFAULT_HEAP_SIZE> {
     /// Size of the heap in bytes.
     heap_size: usize,
     /// First block in the heap.
-    heap: [u8; MAX_HEAP_SIZE],
+    heap: [u8; N],
     /// Pointer to the next free space.
     next_free: *mut u8,
     /// Explicitly linked free list.
-    free_list_head: BlockPointer,
+    free_list: FreeList<Block>,
     /// Number of blocks allocated.
     num_alloced_blocks: usize, 
     /// Whether the heap has been initialized yet.
     initialized: bool,
 }
 
-unsafe impl Sync for Trollocator {}
-unsafe impl Send for Trollocator {}
+unsafe impl<const N: usize> Sync for Trollocator<N> {}
+unsafe impl<const N: usize> Send for Trollocator<N> {}
+
+impl<const N: usize> Trollocator<N> {
+    /// Fails to compile for heaps that cannot hold a single block, which `heap_init` would
+    /// otherwise underflow on.
+    const HEAP_FITS: () = assert!(N >= MIN_BLOCK_SIZE, "Trollocator heap is too small to hold a single block");
 
-impl Trollocator {
     /// Instantiate a new Trollocator.
     ///
     /// Returns a trollocator instance with a zero heap size, no first block, and an empty free list.
+    /// The heap has to have room for at least one block:
+    ///
+    /// ```compile_fail
+    /// # #![allow(deprecated)]
+    /// let allocator = trolloc::allocator::Trollocator::<8>::new();
+    /// ```
     pub const fn new() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::HEAP_FITS;
+
         Trollocator {
-            heap_size: MAX_HEAP_SIZE,
-            heap: [0; MAX_HEAP_SIZE],
+            heap_size: N,
+            heap: [0; N],
             next_free: core::ptr::null_mut(),
-            free_list_head: core::ptr::null_mut(),
+            free_list: FreeList::new(),
             num_alloced_blocks: 0,
             initialized: false,
         }
@@ -110,6 +116,11 @@ impl Trollocator {
     }
 
     /// Initialize the heap.
+    ///
+    /// # Safety
+    ///
+    /// Must be called exactly once, before anything is allocated, after the allocator has
+    /// been moved to where it is going to stay.
     pub unsafe fn heap_init(&mut self) { 
         // Initialize heap
         self.initialized = true;
@@ -117,7 +128,7 @@ impl Trollocator {
         // Make entire heap into one block
         *(Self::as_block_ptr(self.heap.as_mut_ptr() as usize)) = Block {
             header: BlockHeader { size: self.heap_size - HEADER_SIZE, prev: core::ptr::null_mut(), free: true },
-            free_node: FreeNode { prev: core::ptr::null_mut(), next: core::ptr::null_mut() }
+            free_node: FreeNode::new()
         };
 
         // Add block to the free list so it can be returned in a malloc call
@@ -128,13 +139,17 @@ impl Trollocator {
     }
 
     /// Heap teardown.
+    ///
+    /// # Safety
+    ///
+    /// Nothing allocated from this heap may be used afterwards.
     pub unsafe fn heap_destroy(&mut self) {
         // free(self.first_block as *mut u8);
     }
 
     /// Check whether a block fits a request size or not.
     unsafe fn block_fits(block: BlockPointer, size: usize) -> bool {
-        ((*block).header.size >= size) && ((size % ALIGNMENT) == 0)
+        ((*block).header.size >= size) && size.is_multiple_of(ALIGNMENT)
     }
 
     /// Check whether a block is free or not.
@@ -159,35 +174,12 @@ impl Trollocator {
 
     /// Remove a memory region from the free list.
     unsafe fn free_list_remove(&mut self, block_ptr: BlockPointer) {
-        let free_prev = (*block_ptr).free_node.prev;
-        let free_next = (*block_ptr).free_node.next;
-
-        if !free_prev.is_null() {
-            (*free_prev).free_node.next = free_next;
-        }
-
-        if !free_next.is_null() {
-            (*free_next).free_node.next = free_prev;
-        }
+        self.free_list.remove(block_ptr);
     }
 
     /// Add a memory region to the free list.
     unsafe fn free_list_add(&mut self, block_ptr: BlockPointer) {
-        if self.free_list_head.is_null() {
-            // The free list is currently empty, so this is now the only block in the free list.
-            self.free_list_head = block_ptr;
-        } else {
-            // Add at free list head
-            let old_head = self.free_list_head;
-
-            // Update free list head
-            self.free_list_head = block_ptr;
-            (*self.free_list_head).free_node.next = old_head;
-            (*self.free_list_head).free_node.prev = core::ptr::null_mut();
-
-            // Update old head's previous
-            (*old_head).free_node.prev = self.free_list_head;
-        }
+        self.free_list.push_front(block_ptr);
     }
 
     /// Re-interpret an address as a block pointer. Don't misuse this. 🙂
@@ -199,23 +191,11 @@ impl Trollocator {
     /// 
     /// Returns a pointer to the block that we are going to allocate as well as its actual start address.
     unsafe fn search_free_list(&mut self, size: usize) -> Option<BlockPointer> {
-        // Use find first free
-        let mut curr = self.free_list_head;
-
-        // Search all free blocks
-        while !curr.is_null() {
+        // Use find first free, searching all free blocks
+        self.free_list.iter().find(|&block| {
             // Check whether this meets size requirements
-            if Self::block_fits(curr, size) && Self::is_free(curr) {
-                // Block fits!
-                return Some(curr);
-            } else {
-                // Move curr forward
-                curr = (*curr).free_node.next;
-            }
-        }
-
-        // Could not find a fitting block
-        None
+            Self::block_fits(block, size) && Self::is_free(block)
+        })
     }
 
     /// Coalesce around a block.
@@ -264,7 +244,7 @@ impl Trollocator {
             .pad_to_align();
         
         (
-            lyt.size().max(mem::size_of::<FreeNode>()),
+            lyt.size().max(mem::size_of::<FreeNode<Block>>()),
             lyt.align()
         )
     }
@@ -293,11 +273,13 @@ impl Trollocator {
     }
 
     /// Allocate a [Block] of memory with the given layout.
+    ///
+    /// # Safety
+    ///
+    /// The heap must have been [initialized](Trollocator::heap_init).
     pub unsafe fn malloc(&mut self, layout: core::alloc::Layout) -> *mut u8 {
         // Align layout to block size
-        let actual_layout = Self::align(layout);
-        let req_size = actual_layout.0;
-        let req_align = actual_layout.1;
+        let req_size = Self::align(layout).0;
 
         // Actually allocate
         if let Some(fitting_block) = self.search_free_list(req_size) {
@@ -312,7 +294,7 @@ impl Trollocator {
                 // (offset by the header size of the malloced block itself)
                 *(((fitting_block as usize) + req_size + HEADER_SIZE) as BlockPointer) = Block {
                     header: BlockHeader { size: original_size - (req_size + HEADER_SIZE), prev: fitting_block, free: true },
-                    free_node: FreeNode { prev: core::ptr::null_mut(), next: core::ptr::null_mut() }
+                    free_node: FreeNode::new()
                 };
 
                 // Add this new block to the free list
@@ -330,21 +312,29 @@ impl Trollocator {
             self.num_alloced_blocks += 1;
 
             // Trolling
+            if !cfg!(feature = "trolling") {
+                return block_address;
+            }
+
             let rand_result: usize = xorshift(block_address as usize);
             let randex: usize = rand_result  % self.num_alloced_blocks;
-            let rand_bit: usize = (rand_result % (core::mem::size_of::<usize>() * 8)).checked_sub(1).unwrap_or(0);
+            let rand_bit: usize = (rand_result % (core::mem::size_of::<usize>() * 8)).saturating_sub(1);
             if ((randex & (1 << rand_bit)) >> rand_bit) == 1 {
                 self.free_random_block(randex);
             }
 
             // Return the malloced block
-            return block_address;
+            block_address
         } else {
-            return core::ptr::null_mut();
+            core::ptr::null_mut()
         }
     }
 
     /// Reallocate a [Block] of memory. The pointer argument must be the same pointer that [`malloc`](crate::malloc) returned.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated by this allocator and not freed since.
     pub unsafe fn realloc(&mut self, ptr: *mut u8, layout: core::alloc::Layout) -> *mut u8 {
         // The lazy way:
         // 1. Malloc new block.
@@ -373,6 +363,10 @@ impl Trollocator {
     }
 
     /// Free a block of allocated memory. The argument must be the same pointer that `malloc` returned.
+    ///
+    /// # Safety
+    ///
+    /// `ptr` must have been allocated by this allocator and not freed since.
     pub unsafe fn free(&mut self, ptr: *mut u8) {
         // First move back to block pointer
         let block = Self::payload_to_block(ptr as usize);
@@ -380,7 +374,7 @@ impl Trollocator {
         // Mark block as free
         (*block).header.free = true;
 
-        self.num_alloced_blocks = self.num_alloced_blocks.checked_sub(1).unwrap_or(0);
+        self.num_alloced_blocks = self.num_alloced_blocks.saturating_sub(1);
 
         // Now add to free list
         self.free_list_add(block);
@@ -391,11 +385,17 @@ impl Trollocator {
 
 }
 
+impl<const N: usize> Default for Trollocator<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 #[cfg(feature = "std")]
 use core::ops::Drop;
 
 #[cfg(feature = "std")]
-impl Drop for Trollocator {
+impl<const N: usize> Drop for Trollocator<N> {
     /// Drop should just call [teardown](crate::allocator::Trollocator::heap_destroy).
     fn drop(&mut self) {
         unsafe { self.heap_destroy(); }
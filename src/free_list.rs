@@ -0,0 +1,117 @@
+//! # Free List
+//!
+//! An intrusive, explicitly doubly-linked list of free blocks, shared by
+//! [`allocator`](crate::allocator) and [`gjallocator`](crate::gjallocator).
+//!
+//! The links live inside the free blocks themselves, overlapping the payload that a free block
+//! has no use for, so the list never needs to allocate. The list itself is just a pointer to its head.
+//!
+//! Everything here is unchecked. Pushing a node that is already in a list, or removing one that
+//! is not, corrupts the list. Trolloc only wants to corrupt things on purpose.
+
+use core::marker::PhantomData;
+
+#[repr(C)]
+/// Free list linkage pointers, overlaps payload space.
+pub(crate) struct FreeNode<T> {
+    /// Previous free block.
+    pub(crate) prev: *mut T,
+    /// Next free block.
+    pub(crate) next: *mut T,
+}
+
+impl<T> FreeNode<T> {
+    /// Unlinked node.
+    pub(crate) const fn new() -> Self {
+        Self { prev: core::ptr::null_mut(), next: core::ptr::null_mut() }
+    }
+}
+
+/// Something that carries a [`FreeNode`] and can therefore be put in a [`FreeList`].
+///
+/// # Safety
+///
+/// `links` must return a pointer to a [`FreeNode`] inside `node`, which is not used for anything
+/// else for as long as `node` is in a list.
+pub(crate) unsafe trait Linked: Sized {
+    /// Get the linkage pointers of a node.
+    unsafe fn links(node: *mut Self) -> *mut FreeNode<Self>;
+}
+
+#[repr(C)]
+/// Intrusive doubly-linked list.
+pub(crate) struct FreeList<T> {
+    /// First node, or null if the list is empty.
+    head: *mut T,
+}
+
+impl<T: Linked> FreeList<T> {
+    /// Create an empty list.
+    pub(crate) const fn new() -> Self {
+        Self { head: core::ptr::null_mut() }
+    }
+
+    /// Add a node at the head of the list.
+    pub(crate) unsafe fn push_front(&mut self, node: *mut T) {
+        let links = T::links(node);
+        (*links).prev = core::ptr::null_mut();
+        (*links).next = self.head;
+
+        // Update old head's previous, unless the list was empty
+        if !self.head.is_null() {
+            (*T::links(self.head)).prev = node;
+        }
+
+        self.head = node;
+    }
+
+    /// Remove a node from the list. The node must currently be in this list.
+    pub(crate) unsafe fn remove(&mut self, node: *mut T) {
+        let links = T::links(node);
+        let prev = (*links).prev;
+        let next = (*links).next;
+
+        if prev.is_null() {
+            // Removing the head
+            self.head = next;
+        } else {
+            (*T::links(prev)).next = next;
+        }
+
+        if !next.is_null() {
+            (*T::links(next)).prev = prev;
+        }
+
+        // Do not leave dangling links behind in the node
+        (*links).prev = core::ptr::null_mut();
+        (*links).next = core::ptr::null_mut();
+    }
+
+    /// Iterate over the nodes from head to tail.
+    ///
+    /// The list must not be modified while iterating.
+    pub(crate) unsafe fn iter(&self) -> Iter<'_, T> {
+        Iter { curr: self.head, _list: PhantomData }
+    }
+}
+
+/// Iterator over the nodes of a [`FreeList`], from head to tail.
+pub(crate) struct Iter<'a, T> {
+    curr: *mut T,
+    _list: PhantomData<&'a FreeList<T>>,
+}
+
+impl<T: Linked> Iterator for Iter<'_, T> {
+    type Item = *mut T;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.curr.is_null() {
+            return None;
+        }
+
+        let node = self.curr;
+        // Move curr forward
+        self.curr = unsafe { (*T::links(node)).next };
+        Some(node)
+    }
+}
@@ -10,11 +10,24 @@
 //! The implementation is relatively simple. Trolloc features:
 //! - An explicit, doubly-linked free block list.
 //! - First-fit search algorithm.
+//! - Alignments bigger than a word, by splitting off the front of a free block.
 //! - A statically-allocated heap, with heap metadata contained at the head of the heap.
+//!     - Or a caller-provided memory region instead, see [`Trollocator::init`] and [`Trollocator::from_region`].
 //!     - **Note:** An erratum in the writeup mentions that this is a stack-based heap, it is not, anymore. That is true for [`allocator`](crate::allocator).
+//! - Starting over with [`Trollocator::reset`], to reuse one allocator across many runs.
 //! - Free block coalescence.
 //! - Block splitting, to reduce fragmentation.
-//! - Wasting an entire 8-bit boolean in block metadata to mark a block freewhen 1 bit would suffice, to increase fragmentation.
+//! - In-place `realloc`, shrinking or growing into a free neighbour before resorting to moving the block.
+//! - With the `alloc` feature, a [`HeapSnapshot`] of the physical block map, which can be
+//!   exported to JSON or drawn as SVG, and a [`LeakReport`] of everything still allocated.
+//! - With the `stats` feature, allocation `Stats` for comparing runs, kept up to date by every
+//!   call instead of walking the heap. Mostly: see `Trollocator::stats` for the one exception.
+//! - With the `nightly` feature, the unstable [`Allocator`](core::alloc::Allocator) trait, to troll
+//!   a single `Vec::new_in` or `Box::new_in` without replacing the global allocator.
+//! - Boundary tags: every block ends in a footer repeating its size, so the previous physical
+//!   block can be found (and coalesced with) in constant time without storing a pointer to it.
+//! - The free bit packed into the low bit of the block size, like in the textbooks. This used
+//!   to waste an entire 8-bit boolean to increase fragmentation, but our juniors kept asking.
 //! 
 //! The trolling algorithm is boringly simple. Essentially, the `alloc` function does the
 //! following to troll users:
@@ -23,62 +36,112 @@
 //! - Generate a random number with [`wyrand`](crate::wyrand) and use this to determine an allocated block to free.
 //! - If some conditions are met, free that block before returning the allocated block.
 //! 
+//! All of which a [`TrollPolicy`] can change: the seed, the conditions and what is done to the block,
+//! such as flipping a few of its bits instead, swapping its contents with another block's, or
+//! planting a time bomb in it that frees it some number of allocations or frees later. Some trolls do not pick a victim at all, and
+//! happen to the allocation being made instead, like, if asked for, handing out one that is a few
+//! bytes short, or one that is not aligned. Which is why a payload pointer that is not on a word
+//! still finds its block. `realloc` gets trolled too, by being lazy about it: copying only some
+//! of the contents over, not growing at all, or leaving the old block allocated. So does
+//! `alloc_zeroed`, by leaving some of the old contents where they were, and `dealloc`, by not
+//! freeing anything at all. Both can also be made to take their time, if asked for. And, if asked for, `alloc`
+//! can hand out part of a live block as an alias, kept track of on the side so that freeing it
+//! does not free the block it is part of.
+//! 
 //! Note that the trolling algorithm doesn't actually run if a fitting block to allocate
-//! cannot be found.
+//! cannot be found, or at all without the `trolling` feature.
 //! 
 //! Other things to consider:
 //! - This implementation requires horribly misusing types, forcefully reinterpreting
-//! values all over the place in order to achieve its evil ends.
+//!   values all over the place in order to achieve its evil ends.
 //! - In order to make unsafe reinterpret casts actually work, everything is repr(C).
 //! - The whole implementation is just a bunch of unsafe and unchecked pointer stuff, really.
 //! 
 //! In spite of all that, if you remove the trolling algorithm, this should theoretically
 //! work as an allocator for small applications that prefer horribly unoptimized allocators.
 
-const TROLLING_ON: bool = true;
+const TROLLING_ON: bool = cfg!(feature = "trolling");
 const HEADER_SIZE: usize = core::mem::size_of::<BlockHeader>();
-const MIN_BLOCK_SIZE: usize = core::mem::size_of::<Block>();
-const MAX_HEAP_SIZE: usize = 0x100000;
+const FOOTER_SIZE: usize = core::mem::size_of::<BlockFooter>();
+const MIN_BLOCK_SIZE: usize = core::mem::size_of::<Block>() + FOOTER_SIZE;
+/// Low bit of a block size, set when the block is free.
+const FREE_BIT: usize = 0b1;
+/// Second lowest bit of a block size, set when (some of) a free block was freed by trolling
+/// rather than by its owner, who might not be done with it, or when an allocated block is only
+/// still allocated because trolling swallowed its owner's free.
+const TROLLED_BIT: usize = 0b10;
+/// Third lowest bit of a block size, set when an allocated block has a time bomb in it.
+const ARMED_BIT: usize = 0b100;
+/// Most bits a single bit flip troll flips.
+const MAX_BIT_FLIPS: u64 = 4;
+/// Most time bombs that can be ticking at once.
+const MAX_TIME_BOMBS: usize = 8;
+/// Longest fuse on a time bomb.
+const MAX_FUSE: u64 = 64;
+/// Most aliases that can be handed out at once.
+const MAX_ALIASES: usize = 8;
+/// Buckets in the histogram of stalls, one per power of two microseconds.
+const STALL_BUCKETS: usize = 16;
+/// Spins that take about a microsecond, give or take a CPU. Only for stalling without `std`,
+/// which can sleep instead.
+#[cfg(not(feature = "std"))]
+const SPINS_PER_MICRO: u64 = 64;
+/// Low bits of a block size that are flags instead of size, since sizes are multiples of [`ALIGNMENT`].
+const FLAG_MASK: usize = ALIGNMENT - 1;
+const METADATA_SIZE: usize = core::mem::size_of::<TrollocatorMetadata>();
+/// Heap size of a [`Trollocator`] when none is given, 1 MiB.
+pub const DEFAULT_HEAP_SIZE: usize = 0x100000;
 pub(crate) const ALIGNMENT: usize = 8;
 
 use core::{alloc::{Layout, GlobalAlloc}, mem::{self}, cell::UnsafeCell};
 
-use crate::wyrand;
+use crate::{free_list::{FreeList, FreeNode, Linked}, policy::{Fuse, TrollAction, TrollDetail, TrollPolicy, Troller}};
+
+#[cfg(feature = "nightly")]
+use core::{alloc::{Allocator, AllocError}, ptr::NonNull};
+
+#[cfg(feature = "alloc")]
+use {alloc::vec::Vec, crate::{leaks::{Leak, LeakReport}, snapshot::{BlockSnapshot, BlockState, HeapSnapshot}}};
 
 type BlockPointer = *mut Block;
 
 #[repr(C)]
 /// Block header.
 struct BlockHeader {
-    /// Leasable size of this block.
+    /// Leasable size of this block, with [`FREE_BIT`] set if the block is currently free and
+    /// [`TROLLED_BIT`] set if trolling got it that way, or kept it from getting that way.
     size: usize,
-    /// Pointer to previous physical block.
-    prev: BlockPointer,
-    /// Whether block is currently free.
-    free: bool,
+    /// Allocation sequence number of the block, for telling leaks apart. Stale in free blocks.
+    /// Only kept for leak reports, so that blocks stay a word smaller without them.
+    #[cfg(feature = "alloc")]
+    seq: usize,
 }
 
 #[repr(C)]
-/// Free list linkage pointers, overlaps payload space.
-struct FreeNode {
-    /// Previous free block.
-    prev: BlockPointer,
-    /// Next free block.
-    next: BlockPointer,
+/// Block footer (boundary tag), sits right after the payload.
+struct BlockFooter {
+    /// Copy of the header's size and flags.
+    size: usize,
 }
 
 #[repr(C)]
 /// Smallest unit of allocator.
 pub struct Block {
-    /// Header data, including size and pointer to previous block start.
+    /// Header data, the size and free bit.
     header: BlockHeader,
     /// Pointers for free blocks.
-    free_node: FreeNode,
+    free_node: FreeNode<Block>,
+}
+
+unsafe impl Linked for Block {
+    unsafe fn links(node: *mut Self) -> *mut FreeNode<Self> {
+        core::ptr::addr_of_mut!((*node).free_node)
+    }
 }
 
 /// Metadata heading for the heap.
 /// 
-/// Size = 48 bytes, align 8 bytes.
+/// Size = 376 bytes (more with the `stats` feature), align 8 bytes.
 #[repr(C)]
 pub struct TrollocatorMetadata {
     /// Size of the heap in bytes.
@@ -88,60 +151,478 @@ pub struct TrollocatorMetadata {
     /// Pointer to the next free space.
     next_free: *mut u8,
     /// Explicitly linked free list.
-    free_list_head: BlockPointer,
+    free_list: FreeList<Block>,
     /// Number of blocks allocated.
     num_alloced_blocks: usize, 
+    /// Number of blocks ever allocated, which is the sequence number of the next one.
+    num_allocations: usize,
+    /// Ticking time bombs. Each one is in an allocated block with [`ARMED_BIT`] set.
+    time_bombs: [TimeBomb; MAX_TIME_BOMBS],
+    /// Aliases handed out and not freed yet, which are not blocks of their own.
+    aliases: [Alias; MAX_ALIASES],
     /// Whether the heap has been initialized yet.
     initialized: bool,
+    /// Running statistics.
+    #[cfg(feature = "stats")]
+    counters: Counters,
 }
 
-#[repr(align(8))]
+#[derive(Clone, Copy)]
+#[repr(C)]
+/// A [`TrollAction::TimeBomb`] waiting to go off.
+struct TimeBomb {
+    /// Payload of the block to free, null if this slot is empty.
+    ptr: *mut u8,
+    /// How many more to count down.
+    countdown: usize,
+    /// What to count.
+    fuse: Fuse,
+}
+
+impl TimeBomb {
+    /// An empty slot.
+    const NONE: Self = Self { ptr: core::ptr::null_mut(), countdown: 0, fuse: Fuse::Allocs };
+}
+
+#[derive(Clone, Copy)]
+#[repr(C)]
+/// A [`TrollAction::Alias`] somewhere inside a live block, handed out as if it were a block.
+struct Alias {
+    /// What was handed out, null if this slot is empty.
+    ptr: *mut u8,
+    /// Size that was asked for.
+    size: usize,
+}
+
+impl Alias {
+    /// An empty slot.
+    const NONE: Self = Self { ptr: core::ptr::null_mut(), size: 0 };
+}
+
+#[derive(Clone, Copy, Default)]
+#[cfg_attr(not(feature = "stats"), allow(dead_code))]
+/// Statistics kept up to date by every call, from which [`Stats`] are made.
+struct Counters {
+    bytes_in_use: usize,
+    peak_bytes_in_use: usize,
+    allocs: usize,
+    frees: usize,
+    reallocs: usize,
+    trolls: usize,
+    swallowed_frees: usize,
+    stalls: [usize; STALL_BUCKETS],
+    free_blocks: usize,
+    /// Size of the largest free block, unless it is stale.
+    largest_free_block: usize,
+    /// Set when the largest free block got used up, until somebody goes looking for the new one.
+    /// Which is [`Trollocator::stats`], walking the free list.
+    largest_free_stale: bool,
+}
+
+#[cfg_attr(not(feature = "stats"), allow(dead_code))]
+impl Counters {
+    /// A block of `size` leasable bytes was handed out.
+    fn allocated(&mut self, size: usize) {
+        self.allocs += 1;
+        self.resized(0, size);
+    }
+
+    /// An allocated block went from `old_size` to `new_size` leasable bytes.
+    fn resized(&mut self, old_size: usize, new_size: usize) {
+        // Saturating, since trolling can free things twice
+        self.bytes_in_use = self.bytes_in_use.saturating_sub(old_size) + new_size;
+        self.peak_bytes_in_use = self.peak_bytes_in_use.max(self.bytes_in_use);
+    }
+
+    /// A new free block of `size` leasable bytes appeared.
+    fn free_block_added(&mut self, size: usize) {
+        self.free_blocks += 1;
+        self.free_block_grown(size);
+    }
+
+    /// A free block grew to `size` leasable bytes.
+    fn free_block_grown(&mut self, size: usize) {
+        // Nothing else is bigger than the largest, even if we lost track of which one that is
+        if size >= self.largest_free_block {
+            self.largest_free_block = size;
+            self.largest_free_stale = false;
+        }
+    }
+
+    /// A free block was merged into a neighbour, which is at least as big now.
+    fn free_block_merged(&mut self) {
+        self.free_blocks = self.free_blocks.saturating_sub(1);
+    }
+
+    /// A free block of `old_size` leasable bytes is gone.
+    fn free_block_removed(&mut self, old_size: usize) {
+        self.free_blocks = self.free_blocks.saturating_sub(1);
+        self.free_block_shrunk(old_size);
+    }
+
+    /// A free block of `old_size` leasable bytes got smaller.
+    fn free_block_shrunk(&mut self, old_size: usize) {
+        if old_size >= self.largest_free_block {
+            self.largest_free_stale = true;
+        }
+    }
+}
+
+#[cfg(feature = "stats")]
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+/// Allocation statistics of a [`Trollocator`], see [`Trollocator::stats`].
+pub struct Stats {
+    /// Leasable bytes in allocated blocks, including whatever rounding up the requests added.
+    pub bytes_in_use: usize,
+    /// Leasable bytes in free blocks.
+    pub bytes_free: usize,
+    /// Highest `bytes_in_use` has ever been.
+    pub peak_bytes_in_use: usize,
+    /// Number of blocks handed out, including by `realloc`s that had to move.
+    pub allocs: usize,
+    /// Number of blocks freed by their owners, including by `realloc`s that had to move.
+    pub frees: usize,
+    /// Number of calls to `realloc`.
+    pub reallocs: usize,
+    /// Number of times trolling did something to a block, like freeing it out from under its
+    /// owner. Trolled.
+    pub trolls: usize,
+    /// Number of frees trolling swallowed, leaving the block allocated. Counted in `trolls` too,
+    /// but not in `frees`.
+    pub swallowed_frees: usize,
+    /// Histogram of the stalls trolling put in: `stalls[i]` counts the ones that took from 2^i
+    /// up to 2^(i+1) microseconds, and the last bucket everything longer too. Counted in
+    /// `trolls` as well.
+    pub stalls: [usize; STALL_BUCKETS],
+    /// Leasable bytes in the largest free block, the biggest allocation that can still succeed.
+    /// The one statistic that can take a walk of the free list to come up with.
+    pub largest_free_block: usize,
+    /// Number of free blocks.
+    pub free_blocks: usize,
+    /// Share of the free bytes that are *not* in the largest free block, from 0 (one big free
+    /// block) to almost 1 (crumbs everywhere).
+    pub fragmentation: f64,
+}
+
+#[repr(C, align(8))]
 /// The allocator.
-pub struct Trollocator {
-    heap: UnsafeCell<[u8; MAX_HEAP_SIZE]>,
+/// 
+/// `N` is the size of the heap in bytes, including the [`TrollocatorMetadata`] at its head.
+/// Bigger workloads can declare a bigger heap, e.g. `static A: Trollocator<{ 64 << 20 }>`.
+/// 
+/// A `Trollocator<0>` has no inline heap at all and hands out nothing until it is given a
+/// memory region with [`init`](Trollocator::init).
+pub struct Trollocator<const N: usize = DEFAULT_HEAP_SIZE> {
+    /// Caller-provided heap region, used instead of the inline heap when not null.
+    region: UnsafeCell<*mut u8>,
+    /// How to troll, and the random number generator to do it with.
+    troller: UnsafeCell<Troller>,
+    heap: UnsafeCell<[u8; N]>,
 }
 
-unsafe impl Sync for Trollocator {}
-unsafe impl Send for Trollocator {}
+unsafe impl<const N: usize> Sync for Trollocator<N> {}
+unsafe impl<const N: usize> Send for Trollocator<N> {}
+
+impl Trollocator<0> {
+    /// Create an allocator whose heap lives in a caller-provided region, such as a buffer in a
+    /// linker section, a shared-memory segment or a test fixture.
+    /// 
+    /// Panics if the region is too small to hold the metadata and a single block.
+    pub fn from_region(region: &'static mut [u8]) -> Self {
+        let allocator = Self::new();
+
+        // SAFETY: the region is ours, forever.
+        let fits = unsafe { allocator.init(region.as_mut_ptr(), region.len()) };
+        assert!(fits, "Trollocator region is too small to hold a single block");
+
+        allocator
+    }
+}
+
+impl<const N: usize> Trollocator<N> {
+    /// Fails to compile for inline heaps that cannot hold the metadata and a single block.
+    const HEAP_FITS: () = assert!(N == 0 || N >= METADATA_SIZE + MIN_BLOCK_SIZE, "Trollocator heap is too small to hold a single block");
 
-impl Trollocator {
     /// Create a new allocator.
     pub const fn new() -> Self {
+        #[allow(clippy::let_unit_value)]
+        let () = Self::HEAP_FITS;
+
         Self {
-            heap: UnsafeCell::new([0; MAX_HEAP_SIZE]),
+            region: UnsafeCell::new(core::ptr::null_mut()),
+            troller: UnsafeCell::new(Troller::new(TrollPolicy::DEFAULT)),
+            heap: UnsafeCell::new([0; N]),
+        }
+    }
+
+    /// Troll by `policy` instead of the [default](TrollPolicy::DEFAULT) one, e.g.
+    /// `static A: Trollocator = Trollocator::new().with_policy(POLICY)`.
+    pub const fn with_policy(self, policy: TrollPolicy) -> Self {
+        Self { troller: UnsafeCell::new(Troller::new(policy)), ..self }
+    }
+
+    /// Troll by `policy` from now on, starting its random number generator over.
+    /// 
+    /// # Safety
+    /// 
+    /// Must not be called while anything else is using the allocator.
+    pub unsafe fn set_policy(&self, policy: TrollPolicy) {
+        *self.troller.get() = Troller::new(policy);
+    }
+
+    /// Use the memory region `start..start + len` as the heap instead of the inline one.
+    /// 
+    /// The region does not need to be aligned or zeroed. Returns `false` and leaves the
+    /// allocator untouched if the region is too small to hold the metadata and a single block.
+    /// 
+    /// # Safety
+    /// 
+    /// The region must be valid for reads and writes and must not be used for anything else for
+    /// as long as the allocator is in use. This must be called before the first allocation, and
+    /// not while anything else is using the allocator: blocks handed out of a previous heap are
+    /// forgotten about.
+    pub unsafe fn init(&self, start: *mut u8, len: usize) -> bool {
+        // Skip ahead to the first aligned address
+        let padding = start.align_offset(ALIGNMENT);
+        if len < padding || len - padding < METADATA_SIZE + MIN_BLOCK_SIZE {
+            return false;
+        }
+
+        let base = start.add(padding);
+        *self.region.get() = base;
+        Self::init_heap(base, len - padding);
+
+        true
+    }
+
+    /// Start over: make the whole heap one big free block again, zero the statistics, and start
+    /// the trolling policy's random number generator over, so that a seeded run can be replayed.
+    /// 
+    /// This is for reusing one allocator, such as a `static` one, across many independent runs
+    /// in the same process. Does nothing to a heap that has not been used yet.
+    /// 
+    /// # Safety
+    /// 
+    /// Every block handed out so far is taken back, so none of them can be used again, and this
+    /// must not be called while anything else is using the allocator. Check with
+    /// [`assert_no_live_allocations`](Trollocator::assert_no_live_allocations) first to be sure.
+    pub unsafe fn reset(&self) {
+        let base = self.heap_base();
+        if base.is_null() || !(*self.get_metadata()).initialized {
+            return;
+        }
+
+        // The heap size was rounded down to a multiple of the alignment last time, so it comes
+        // out the same again
+        Self::init_heap(base, METADATA_SIZE + (*self.get_metadata()).heap_size);
+        self.set_policy((*self.troller.get()).policy);
+    }
+
+    /// Panic if anything handed out by the allocator has not been given back, e.g. before a
+    /// [`reset`](Trollocator::reset). Blocks trolling freed count as given back.
+    #[track_caller]
+    pub fn assert_no_live_allocations(&self) {
+        let live = self.get_alloced_blocks();
+        assert!(live == 0, "{live} allocations are still live");
+    }
+
+    /// Lay out metadata at `base` and make the rest of the `len` bytes one big free block.
+    unsafe fn init_heap(base: *mut u8, len: usize) {
+        let metadata = TrollocatorMetadata::from(base);
+        let heap_size = (len - METADATA_SIZE) / ALIGNMENT * ALIGNMENT;
+        let heap_start = base.add(METADATA_SIZE);
+
+        // Make the heap one big block.
+        let block = Self::as_block_ptr(heap_start as usize);
+        Self::set_block(block, heap_size - HEADER_SIZE - FOOTER_SIZE, FREE_BIT);
+
+        // The region might be garbage, so write the whole thing instead of field by field
+        metadata.write(TrollocatorMetadata {
+            heap_size,
+            heap_start,
+            next_free: heap_start,
+            free_list: FreeList::new(),
+            num_alloced_blocks: 0,
+            num_allocations: 0,
+            time_bombs: [TimeBomb::NONE; MAX_TIME_BOMBS],
+            aliases: [Alias::NONE; MAX_ALIASES],
+            initialized: true,
+            #[cfg(feature = "stats")]
+            counters: Counters {
+                free_blocks: 1,
+                largest_free_block: Self::block_size(block),
+                ..Counters::default()
+            },
+        });
+
+        // Add block to the free list so it can be returned in an alloc call
+        (*metadata).free_list.push_front(block);
+    }
+
+    /// Get the start of the heap, whether it is the inline one or a caller-provided region.
+    /// 
+    /// Returns null if there is no heap at all yet.
+    fn heap_base(&self) -> *mut u8 {
+        let region = unsafe { *self.region.get() };
+
+        if !region.is_null() {
+            region
+        } else if N == 0 {
+            core::ptr::null_mut()
+        } else {
+            self.heap.get().cast::<u8>()
         }
     }
 
     /// Get metadata by just interpreting the heap as a metadata pointer because who cares.
-    const fn get_metadata(&self) -> *mut TrollocatorMetadata {
-        TrollocatorMetadata::from(self.heap.get().cast::<u8>())
+    fn get_metadata(&self) -> *mut TrollocatorMetadata {
+        TrollocatorMetadata::from(self.heap_base())
     }
 
     /// Get the number of leased blocks.
     pub fn get_alloced_blocks(&self) -> usize {
+        if self.heap_base().is_null() {
+            return 0;
+        }
+
         unsafe { (*self.get_metadata()).num_alloced_blocks }
     }
 
     /// Get the heap start as a raw address.
     pub fn heap_start(&self) -> usize {
+        if self.heap_base().is_null() {
+            return 0;
+        }
+
         // First address of the internal heap is the heap start
         unsafe { (*self.get_metadata()).heap_start as usize }
     }
 
     /// Get the heap end as a raw address.
     pub fn heap_end(&self) -> usize {
+        if self.heap_base().is_null() {
+            return 0;
+        }
+
         // Last address is first + size
-        self.heap_start() + MAX_HEAP_SIZE
+        unsafe { self.heap_start() + (*self.get_metadata()).heap_size }
+    }
+
+    /// Get the number of bytes usable in an allocated block, which can be more than was asked for.
+    /// 
+    /// # Safety
+    /// 
+    /// `ptr` must have been returned by this allocator and not freed since.
+    pub unsafe fn usable_size(&self, ptr: *const u8) -> usize {
+        // Aliased by trolling, all there is is what was asked for
+        if let Some(alias) = self.find_alias(ptr) {
+            return alias.size;
+        }
+
+        let block = Self::payload_to_block(ptr as usize);
+        // Misaligned by trolling, the bytes it was pushed past are not usable
+        Self::block_size(block) - (ptr as usize - Self::block_to_payload(block) as usize)
+    }
+
+    /// Get a snapshot of the allocation statistics.
+    /// 
+    /// Everything is kept up to date by every call, without walking the heap. Except for the
+    /// largest free block: when that one gets used up, this walks the free list to find the next
+    /// largest one. A heap that has not been used yet has no statistics at all.
+    ///
+    /// Which makes this O(1) most of the time, but O(n) in the number of free blocks the first
+    /// time after the largest one shrank or got handed out. Keeping the largest free block up to
+    /// date on every split would take a heap of free blocks, and would make every call pay for
+    /// the few that ask. Calling this after every allocation in a loop does pay for it, over and over.
+    #[cfg(feature = "stats")]
+    pub fn stats(&self) -> Stats {
+        if self.heap_base().is_null() || unsafe { !(*self.get_metadata()).initialized } {
+            return Stats::default();
+        }
+
+        unsafe {
+            let metadata = self.get_metadata();
+            let counters = &mut (*metadata).counters;
+
+            if counters.largest_free_stale {
+                counters.largest_free_block = (*metadata).free_list.iter().map(|block| Self::block_size(block)).max().unwrap_or(0);
+                counters.largest_free_stale = false;
+            }
+
+            // Whatever is not in use or taken by headers and footers is free
+            let overhead = ((*metadata).num_alloced_blocks + counters.free_blocks) * (HEADER_SIZE + FOOTER_SIZE);
+            let bytes_free = (*metadata).heap_size.saturating_sub(counters.bytes_in_use + overhead);
+
+            Stats {
+                bytes_in_use: counters.bytes_in_use,
+                bytes_free,
+                peak_bytes_in_use: counters.peak_bytes_in_use,
+                allocs: counters.allocs,
+                frees: counters.frees,
+                reallocs: counters.reallocs,
+                trolls: counters.trolls,
+                swallowed_frees: counters.swallowed_frees,
+                stalls: counters.stalls,
+                largest_free_block: counters.largest_free_block,
+                free_blocks: counters.free_blocks,
+                fragmentation: if bytes_free == 0 {
+                    0.0
+                } else {
+                    1.0 - counters.largest_free_block as f64 / bytes_free as f64
+                },
+            }
+        }
+    }
+
+    /// Update the statistics, with the `stats` feature. Without it, does nothing at all.
+    fn record(&self, update: impl FnOnce(&mut Counters)) {
+        #[cfg(feature = "stats")]
+        unsafe { update(&mut (*self.get_metadata()).counters) };
+        #[cfg(not(feature = "stats"))]
+        let _ = update;
     }
 
     /// Check whether a block fits a request size or not.
     unsafe fn block_fits(block: BlockPointer, size: usize) -> bool {
-        ((*block).header.size >= size) && ((size % ALIGNMENT) == 0)
+        (Self::block_size(block) >= size) && size.is_multiple_of(ALIGNMENT)
     }
 
     /// Check whether a block is free or not.
     unsafe fn is_free(block: BlockPointer) -> bool {
-        (*block).header.free
+        (*block).header.size & FREE_BIT != 0
+    }
+
+    /// Check whether a block has a time bomb in it or not.
+    unsafe fn is_armed(block: BlockPointer) -> bool {
+        (*block).header.size & ARMED_BIT != 0
+    }
+
+    /// Check whether a block was freed by trolling, or had its free swallowed, or not.
+    #[cfg(feature = "alloc")]
+    unsafe fn is_trolled(block: BlockPointer) -> bool {
+        (*block).header.size & TROLLED_BIT != 0
+    }
+
+    /// Get the flag bits of a block.
+    unsafe fn block_flags(block: BlockPointer) -> usize {
+        (*block).header.size & FLAG_MASK
+    }
+
+    /// Get the leasable size of a block, without the flag bits.
+    unsafe fn block_size(block: BlockPointer) -> usize {
+        (*block).header.size & !FLAG_MASK
+    }
+
+    /// Get the footer of a block.
+    unsafe fn block_footer(block: BlockPointer) -> *mut BlockFooter {
+        (block as usize + HEADER_SIZE + Self::block_size(block)) as *mut BlockFooter
+    }
+
+    /// Write the size and flag bits of a block to both its header and its footer.
+    unsafe fn set_block(block: BlockPointer, size: usize, flags: usize) {
+        let tagged_size = size | flags;
+        (*block).header.size = tagged_size;
+        (*Self::block_footer(block)).size = tagged_size;
     }
 
     /// Return payload pointer from block address.
@@ -150,46 +631,38 @@ impl Trollocator {
     }
 
     /// Return block address from payload address.
+    ///
+    /// Payloads always start on a word, so one that does not was misaligned by trolling, and
+    /// belongs to the block whose payload starts on the word before it.
     fn payload_to_block(address: usize) -> BlockPointer {
-        (address - HEADER_SIZE) as BlockPointer
+        (address / ALIGNMENT * ALIGNMENT - HEADER_SIZE) as BlockPointer
     }
 
     /// Get the next physical block from a block pointer.
     unsafe fn next_physical_block(block_ptr: BlockPointer) -> BlockPointer {
-        (block_ptr as usize + HEADER_SIZE + (*block_ptr).header.size) as BlockPointer
+        (block_ptr as usize + HEADER_SIZE + Self::block_size(block_ptr) + FOOTER_SIZE) as BlockPointer
     }
 
-    /// Remove a memory region from the free list.
-    unsafe fn free_list_remove(&self, block_ptr: BlockPointer) {
-        let free_prev = (*block_ptr).free_node.prev;
-        let free_next = (*block_ptr).free_node.next;
-
-        if !free_prev.is_null() {
-            (*free_prev).free_node.next = free_next;
+    /// Get the previous physical block from a block pointer by reading its footer.
+    /// 
+    /// Returns null for the first block in the heap.
+    unsafe fn prev_physical_block(&self, block_ptr: BlockPointer) -> BlockPointer {
+        if block_ptr as usize <= self.heap_start() {
+            return core::ptr::null_mut();
         }
 
-        if !free_next.is_null() {
-            (*free_next).free_node.next = free_prev;
-        }
+        let prev_footer = (block_ptr as usize - FOOTER_SIZE) as *mut BlockFooter;
+        (block_ptr as usize - FOOTER_SIZE - ((*prev_footer).size & !FLAG_MASK) - HEADER_SIZE) as BlockPointer
+    }
+
+    /// Remove a memory region from the free list.
+    unsafe fn free_list_remove(&self, block_ptr: BlockPointer) {
+        (*self.get_metadata()).free_list.remove(block_ptr);
     }
 
     /// Add a memory region to the free list.
     unsafe fn free_list_add(&self, block_ptr: BlockPointer) {
-        if (*self.get_metadata()).free_list_head.is_null() {
-            // The free list is currently empty, so this is now the only block in the free list.
-            (*self.get_metadata()).free_list_head = block_ptr;
-        } else {
-            // Add at free list head
-            let old_head = (*self.get_metadata()).free_list_head;
-
-            // Update free list head
-            (*self.get_metadata()).free_list_head = block_ptr;
-            (*(*self.get_metadata()).free_list_head).free_node.next = old_head;
-            (*(*self.get_metadata()).free_list_head).free_node.prev = core::ptr::null_mut();
-
-            // Update old head's previous
-            (*old_head).free_node.prev = (*self.get_metadata()).free_list_head;
-        }
+        (*self.get_metadata()).free_list.push_front(block_ptr);
     }
 
     /// Re-interpret an address as a block pointer. Don't misuse this. 🙂
@@ -200,36 +673,71 @@ impl Trollocator {
     /// Search the free list for a spot that fits.
     /// 
     /// Returns a pointer to the block that we are going to allocate as well as its actual start address.
-    unsafe fn search_free_list(&self, size: usize) -> Option<BlockPointer> {
-        // Use find first free
-        let mut curr = (*self.get_metadata()).free_list_head;
+    unsafe fn search_free_list(&self, size: usize, align: usize) -> Option<BlockPointer> {
+        // Use find first free, searching all free blocks
+        (*self.get_metadata()).free_list.iter().find(|&block| {
+            // Check whether this meets size requirements, including skipping ahead to align it
+            Self::block_fits(block, size + Self::align_padding(block, align)) && Self::is_free(block)
+        })
+    }
 
-        // Search all free blocks
-        while !curr.is_null() {
-            // Check whether this meets size requirements
-            if Self::block_fits(curr, size) && Self::is_free(curr) {
-                // Block fits!
-                return Some(curr);
-            } else {
-                // Move curr forward
-                curr = (*curr).free_node.next;
-            }
+    /// Get how far into a block an allocation has to start for its payload to be aligned to `align`.
+    /// 
+    /// This is either nothing, or enough to split the skipped part off as a block of its own.
+    fn align_padding(block: BlockPointer, align: usize) -> usize {
+        let payload = Self::block_to_payload(block) as usize;
+        let mut padding = payload.next_multiple_of(align) - payload;
+
+        while padding != 0 && padding < MIN_BLOCK_SIZE {
+            padding += align;
         }
 
-        // Could not find a fitting block
-        None
+        padding
+    }
+
+    /// Split the front off a free block so that the rest has a payload aligned to `align`.
+    /// 
+    /// The front stays in the free list. Returns the rest, which is added to the free list too.
+    unsafe fn align_block(&self, block: BlockPointer, align: usize) -> BlockPointer {
+        let padding = Self::align_padding(block, align);
+        if padding == 0 {
+            return block;
+        }
+
+        // Clamp the front down to just the padding, it stays free where it is
+        let original_size = Self::block_size(block);
+        let flags = Self::block_flags(block);
+        Self::set_block(block, padding - HEADER_SIZE - FOOTER_SIZE, flags);
+
+        // Everything after it is the aligned block
+        let aligned = Self::next_physical_block(block);
+        Self::set_block(aligned, original_size - padding, flags);
+        #[cfg(feature = "alloc")]
+        {
+            (*aligned).header.seq = (*block).header.seq;
+        }
+        self.free_list_add(aligned);
+        self.record(|counters| {
+            counters.free_block_shrunk(original_size);
+            counters.free_block_added(original_size - padding);
+        });
+
+        aligned
     }
 
     /// Coalesce around a block.
     unsafe fn coalesce(&self, mut block: BlockPointer) {
         // Check if the previous block is free. If so, coalesce into it
-        let prev_block = (*block).header.prev;
+        let prev_block = self.prev_physical_block(block);
 
-        if !prev_block.is_null() && (*prev_block).header.free {
-            // Make the previous block include current block's size (and header)
-            (*prev_block).header.size += HEADER_SIZE + (*block).header.size; 
+        if !prev_block.is_null() && Self::is_free(prev_block) {
+            // Make the previous block include current block's size (and header and footer)
+            let merged_size = Self::block_size(prev_block) + FOOTER_SIZE + HEADER_SIZE + Self::block_size(block);
             // Remove the coalesced block from the free list
             self.free_list_remove(block);
+            // Still trolled if either of them was
+            Self::set_block(prev_block, merged_size, Self::block_flags(prev_block) | Self::block_flags(block));
+            self.record(|counters| counters.free_block_merged());
             // Do not add the previous block, it was assumedly already in the free list.
             // Move block pointer to previous block so that next if statement can coalesce both cases
             block = prev_block;
@@ -238,22 +746,87 @@ impl Trollocator {
         // Get the next physical block
         let next_block = Self::next_physical_block(block);
 
-        // Bail out if moved past end of heap
-        if next_block as usize >= self.heap_end() {
-            return;
-        }
-
-        // In case we just coalesced the block behind us, make sure we're pointing to the right spot.
-        (*next_block).header.prev = block;
-        
-        // Otherwise, attempt to coalesce this block too
-        if (*next_block).header.free {
+        // Attempt to coalesce this block too, unless we moved past the end of the heap
+        if (next_block as usize) < self.heap_end() && Self::is_free(next_block) {
             // Coalesce the next block into us
-            (*block).header.size += HEADER_SIZE + (*next_block).header.size;
+            let merged_size = Self::block_size(block) + FOOTER_SIZE + HEADER_SIZE + Self::block_size(next_block);
 
             // Remove from free list
             self.free_list_remove(next_block);
+            Self::set_block(block, merged_size, Self::block_flags(block) | Self::block_flags(next_block));
+            self.record(|counters| counters.free_block_merged());
+        }
+
+        self.record(|counters| counters.free_block_grown(Self::block_size(block)));
+    }
+
+    /// Split a block down to `size` leasable bytes, if the remainder is big enough to be a block.
+    /// 
+    /// The remainder becomes a new free block, which is added to the free list. Returns the
+    /// remainder, if there was one.
+    unsafe fn split_block(&self, block: BlockPointer, size: usize) -> Option<BlockPointer> {
+        let original_size = Self::block_size(block);
+
+        // Not enough room left over for a whole block, keep the slack as internal fragmentation
+        if original_size - size < MIN_BLOCK_SIZE {
+            return None;
+        }
+
+        // This block is now clamped down to the requested size, with a new footer
+        let flags = Self::block_flags(block);
+        Self::set_block(block, size, flags);
+
+        // Create a new block at the address after the clamped block's footer. Plain free, whatever
+        // we were: a trolled free merged into the tail would otherwise hand its blame to every
+        // block split off it from then on
+        let remainder = Self::next_physical_block(block);
+        Self::set_block(remainder, original_size - (size + FOOTER_SIZE + HEADER_SIZE), FREE_BIT);
+
+        // Add the remainder to the free list
+        self.free_list_add(remainder);
+        self.record(|counters| counters.free_block_added(Self::block_size(remainder)));
+
+        Some(remainder)
+    }
+
+    /// Try to resize an allocated block without moving it.
+    /// 
+    /// Shrinking always succeeds, splitting off the tail as a free block when it is big enough.
+    /// Growing succeeds only when the next physical block is free and, together with this one,
+    /// large enough to hold `size` bytes.
+    unsafe fn resize_in_place(&self, block: BlockPointer, size: usize) -> bool {
+        let old_size = Self::block_size(block);
+
+        if old_size >= size {
+            // Shrink (or stay the same), merging the freed tail with a free next block
+            if let Some(remainder) = self.split_block(block, size) {
+                self.coalesce(remainder);
+            }
+            self.record(|counters| counters.resized(old_size, Self::block_size(block)));
+            return true;
         }
+
+        let next_block = Self::next_physical_block(block);
+
+        // Can only grow into a free block that is still inside the heap
+        if next_block as usize >= self.heap_end() || !Self::is_free(next_block) {
+            return false;
+        }
+
+        let combined_size = Self::block_size(block) + FOOTER_SIZE + HEADER_SIZE + Self::block_size(next_block);
+        if combined_size < size {
+            return false;
+        }
+
+        // Absorb the next block entirely
+        self.free_list_remove(next_block);
+        self.record(|counters| counters.free_block_removed(Self::block_size(next_block)));
+        Self::set_block(block, combined_size, Self::block_flags(block));
+
+        // Give back whatever we do not need
+        self.split_block(block, size);
+        self.record(|counters| counters.resized(old_size, Self::block_size(block)));
+        true
     }
 
     /// Align a layout to Block size.
@@ -266,35 +839,450 @@ impl Trollocator {
             .pad_to_align();
         
         (
-            lyt.size().max(mem::size_of::<FreeNode>()),
+            lyt.size().max(mem::size_of::<FreeNode<Block>>()),
             lyt.align()
         )
     }
 
     /// Print the heap to stderr for debugging.
+    #[cfg(feature = "std")]
     pub fn print_heap(&self) {
         unsafe {
             let mut curr_block_ptr: BlockPointer = Self::as_block_ptr(self.heap_start());
             let mut curr_block_index: usize = 0;
 
             while (curr_block_ptr as usize) < (self.heap_end()) {
-                eprintln!("--+ {} @ {:p} (size: {}, free: {})", curr_block_index, curr_block_ptr, (*curr_block_ptr).header.size, (*curr_block_ptr).header.free);
+                eprintln!("--+ {} @ {:p} (size: {}, free: {})", curr_block_index, curr_block_ptr, Self::block_size(curr_block_ptr), Self::is_free(curr_block_ptr));
                 curr_block_ptr = Self::next_physical_block(curr_block_ptr);
                 curr_block_index += 1;
             }
         }
     }
 
+    /// Take a [`HeapSnapshot`] of the physical block map.
+    /// 
+    /// The snapshot is allocated from the global allocator, which is fine even if that is this
+    /// very heap: room for every block is made before looking at any of them.
+    #[cfg(feature = "alloc")]
+    pub fn snapshot(&self) -> HeapSnapshot {
+        if self.heap_base().is_null() || unsafe { !(*self.get_metadata()).initialized } {
+            return HeapSnapshot::default();
+        }
+
+        unsafe {
+            // Making room can change the heap too, but handing out one block turns at most one
+            // free block into three
+            let mut blocks = Vec::with_capacity(self.physical_blocks().count() + 2);
+
+            for block in self.physical_blocks() {
+                if blocks.len() == blocks.capacity() {
+                    break;
+                }
+
+                let state = if !Self::is_free(block) {
+                    BlockState::Allocated
+                } else if Self::is_trolled(block) {
+                    BlockState::Trolled
+                } else {
+                    BlockState::Free
+                };
+
+                blocks.push(BlockSnapshot {
+                    offset: block as usize - self.heap_start(),
+                    size: Self::block_size(block),
+                    state,
+                });
+            }
+
+            HeapSnapshot { heap_size: (*self.get_metadata()).heap_size, blocks }
+        }
+    }
+
+    /// List every block nobody gave back: the ones still allocated, and the ones trolling freed
+    /// and nobody has taken since.
+    /// 
+    /// Like [`snapshot`](Trollocator::snapshot), this is fine to call on the global allocator.
+    /// The report's own allocation is left out of it.
+    #[cfg(feature = "alloc")]
+    pub fn leak_report(&self) -> LeakReport {
+        if self.heap_base().is_null() || unsafe { !(*self.get_metadata()).initialized } {
+            return LeakReport::default();
+        }
+
+        // Same deal as snapshots, room first
+        let mut leaks = Vec::with_capacity(self.leaks().count() + 2);
+        let own = leaks.as_ptr() as usize;
+
+        for leak in self.leaks().filter(|leak| leak.address != own) {
+            if leaks.len() == leaks.capacity() {
+                break;
+            }
+
+            leaks.push(leak);
+        }
+
+        LeakReport { leaks }
+    }
+
+    /// Print a [`leak_report`](Trollocator::leak_report) to stderr when the program exits, e.g.
+    /// right at the start of `main` for the global allocator.
+    /// 
+    /// Only one allocator per program can do this. Returns whether that is this one.
+    #[cfg(feature = "std")]
+    pub fn report_leaks_at_exit(&'static self) -> bool {
+        crate::leaks::report_at_exit(self)
+    }
+
+    /// Iterate over the leaks in the heap, without allocating.
+    #[cfg(feature = "alloc")]
+    pub(crate) fn leaks(&self) -> impl Iterator<Item = Leak> + Clone + '_ {
+        let initialized = !self.heap_base().is_null() && unsafe { (*self.get_metadata()).initialized };
+
+        unsafe { self.physical_blocks() }
+            .take_while(move |_| initialized)
+            .filter(|&block| unsafe { !Self::is_free(block) || Self::is_trolled(block) })
+            .map(|block| unsafe {
+                Leak {
+                    address: Self::block_to_payload(block) as usize,
+                    size: Self::block_size(block),
+                    seq: (*block).header.seq,
+                    trolled: Self::is_trolled(block),
+                }
+            })
+    }
+
+    /// Iterate over every block in the heap, in address order.
+    unsafe fn physical_blocks(&self) -> impl Iterator<Item = BlockPointer> + Clone + '_ {
+        let heap_end = self.heap_end();
+
+        // Stopping before the end, not at it: there might be nothing mapped there to read a size from
+        core::iter::successors(Some(Self::as_block_ptr(self.heap_start())), move |&block| {
+            let next = unsafe { Self::next_physical_block(block) };
+            ((next as usize) < heap_end).then_some(next)
+        })
+    }
+
+    /// Check the heap for corruption.
+    /// 
+    /// Walks every physical block, checking that its header and footer agree, that the blocks
+    /// tile the heap exactly and that no two free blocks were left uncoalesced. Then walks the
+    /// free list, checking that it holds every free block exactly once and nothing else.
+    /// 
+    /// Returns what is wrong with the heap, if anything. This is best effort: a heap that is
+    /// corrupted badly enough can still take the check down with it.
+    pub fn check_heap(&self) -> Result<(), &'static str> {
+        if self.heap_base().is_null() || unsafe { !(*self.get_metadata()).initialized } {
+            // Nothing to corrupt yet
+            return Ok(());
+        }
+
+        unsafe {
+            let metadata = self.get_metadata();
+            let mut curr_block_ptr: BlockPointer = Self::as_block_ptr(self.heap_start());
+            let mut free_blocks: usize = 0;
+            let mut alloced_blocks: usize = 0;
+            let mut bytes_in_use: usize = 0;
+            let mut largest_free_block: usize = 0;
+            let mut prev_free = false;
+
+            while (curr_block_ptr as usize) < self.heap_end() {
+                if Self::next_physical_block(curr_block_ptr) as usize > self.heap_end() {
+                    return Err("block runs past the end of the heap");
+                }
+
+                if (*Self::block_footer(curr_block_ptr)).size != (*curr_block_ptr).header.size {
+                    return Err("block header and footer disagree");
+                }
+
+                let free = Self::is_free(curr_block_ptr);
+                if free && Self::is_armed(curr_block_ptr) {
+                    return Err("free block has a time bomb in it");
+                }
+
+                if free && prev_free {
+                    return Err("neighbouring free blocks were not coalesced");
+                }
+
+                if free {
+                    free_blocks += 1;
+                    largest_free_block = largest_free_block.max(Self::block_size(curr_block_ptr));
+                } else {
+                    alloced_blocks += 1;
+                    bytes_in_use += Self::block_size(curr_block_ptr);
+                }
+
+                prev_free = free;
+                curr_block_ptr = Self::next_physical_block(curr_block_ptr);
+            }
+
+            if alloced_blocks != (*metadata).num_alloced_blocks {
+                return Err("allocated block count does not match the heap");
+            }
+
+            let time_bombs = (*metadata).time_bombs.iter().filter(|bomb| !bomb.ptr.is_null());
+            if time_bombs.clone().any(|bomb| !Self::is_armed(Self::payload_to_block(bomb.ptr as usize))) {
+                return Err("time bomb in a block that is not armed");
+            }
+
+            let aliases = (*metadata).aliases.iter().filter(|alias| !alias.ptr.is_null());
+            if aliases.clone().any(|alias| !(self.heap_start()..self.heap_end()).contains(&(alias.ptr as usize))) {
+                return Err("alias outside the heap");
+            }
+
+            // Every node has exactly one next, so a list that is no longer than the number of
+            // free blocks and only holds free blocks holds each of them exactly once.
+            let mut listed_blocks: usize = 0;
+            for block in (*metadata).free_list.iter() {
+                if listed_blocks == free_blocks {
+                    return Err("free list is longer than the number of free blocks");
+                }
+
+                if !(self.heap_start()..self.heap_end()).contains(&(block as usize)) {
+                    return Err("free list points outside the heap");
+                }
+
+                if !Self::is_free(block) {
+                    return Err("allocated block in the free list");
+                }
+
+                listed_blocks += 1;
+            }
+
+            if listed_blocks != free_blocks {
+                return Err("free block missing from the free list");
+            }
+
+            let mut stats_match = true;
+            self.record(|counters| {
+                stats_match = counters.free_blocks == free_blocks
+                    && counters.bytes_in_use == bytes_in_use
+                    && (counters.largest_free_stale || counters.largest_free_block == largest_free_block);
+            });
+            if !stats_match {
+                return Err("statistics do not match the heap");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// With the `debug-checks` feature, panic if the heap is corrupted.
+    /// 
+    /// The panic itself is likely to allocate from the corrupted heap, so expect an abort.
+    fn debug_check(&self) {
+        if cfg!(feature = "debug-checks") {
+            if let Err(problem) = self.check_heap() {
+                panic!("trolloc heap is corrupted: {}", problem);
+            }
+        }
+    }
+
+    /// Free an allocated block, whether its owner asked for it or not.
+    unsafe fn release(&self, ptr: *mut u8, trolled: bool) {
+        // First move back to block pointer
+        let block = Self::payload_to_block(ptr as usize);
+        let size = Self::block_size(block);
+
+        // Freed early is still freed, the bomb can go. Bombs are planted at the real payload,
+        // not wherever a misaligned pointer points.
+        if Self::is_armed(block) {
+            self.disarm(Self::block_to_payload(block));
+        }
+
+        // Mark block as free
+        Self::set_block(block, size, if trolled { FREE_BIT | TROLLED_BIT } else { FREE_BIT });
+
+        (*self.get_metadata()).num_alloced_blocks = (*self.get_metadata()).num_alloced_blocks.saturating_sub(1);
+        self.record(|counters| {
+            counters.resized(size, 0);
+            counters.free_block_added(size);
+        });
+
+        // Now add to free list
+        self.free_list_add(block);
+
+        // Coalesce this block
+        self.coalesce(block);
+    }
+
     // ---------------------------- TROLLING ----------------------------
 
+    /// Plant a time bomb in the allocated block at `ptr`, to free it `after` counts of `fuse`
+    /// from now. Returns `false` if there is no room for another bomb, or one is already there.
+    unsafe fn arm(&self, ptr: *mut u8, after: usize, fuse: Fuse) -> bool {
+        let block = Self::payload_to_block(ptr as usize);
+        let time_bombs = &mut (*self.get_metadata()).time_bombs;
+
+        match time_bombs.iter_mut().find(|bomb| bomb.ptr.is_null()) {
+            Some(slot) if !Self::is_armed(block) => {
+                *slot = TimeBomb { ptr, countdown: after, fuse };
+                Self::set_block(block, Self::block_size(block), Self::block_flags(block) | ARMED_BIT);
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Take the time bomb out of the block at `ptr`, which is about to be freed anyway.
+    unsafe fn disarm(&self, ptr: *mut u8) {
+        let time_bombs = &mut (*self.get_metadata()).time_bombs;
+
+        if let Some(bomb) = time_bombs.iter_mut().find(|bomb| bomb.ptr == ptr) {
+            *bomb = TimeBomb::NONE;
+        }
+    }
+
+    /// Count down the time bombs on `fuse`, setting off the ones that reach zero.
+    unsafe fn tick(&self, fuse: Fuse) {
+        for index in 0..MAX_TIME_BOMBS {
+            let bomb = &mut (*self.get_metadata()).time_bombs[index];
+            if bomb.ptr.is_null() || bomb.fuse != fuse {
+                continue;
+            }
+
+            bomb.countdown = bomb.countdown.saturating_sub(1);
+            if bomb.countdown == 0 {
+                let ptr = bomb.ptr;
+                let size = Self::block_size(Self::payload_to_block(ptr as usize));
+
+                // Boom. Long distance trolling.
+                self.release(ptr, true);
+                (*self.troller.get()).log(TrollAction::TimeBomb, ptr, size, TrollDetail::Detonated);
+            }
+        }
+    }
+
+    /// Hand out somewhere inside a random live block for `layout`, instead of a block of its
+    /// own. Returns `None` if there is no room for another alias, or nowhere it fits.
+    unsafe fn alias(&self, layout: Layout, entropy: u64) -> Option<*mut u8> {
+        let metadata = self.get_metadata();
+        let slot = (*metadata).aliases.iter().position(|alias| alias.ptr.is_null())?;
+        let live = (*metadata).num_alloced_blocks;
+        if live == 0 {
+            return None;
+        }
+
+        let troller = &mut *self.troller.get();
+        let owner = self.get_block_by_index(troller.next(entropy) as usize % live);
+        let room = Self::block_size(Self::payload_to_block(owner as usize)).checked_sub(layout.size())?;
+        // Payloads are only ever word aligned
+        if !(owner as usize).is_multiple_of(layout.align()) {
+            return None;
+        }
+
+        // Anywhere it fits, aligned like it asked
+        let offset = troller.next(entropy) as usize % (room / layout.align() + 1) * layout.align();
+        let ptr = owner.add(offset);
+        (*metadata).aliases[slot] = Alias { ptr, size: layout.size() };
+
+        self.record(|counters| {
+            counters.allocs += 1;
+            counters.trolls += 1;
+        });
+        troller.log(TrollAction::Alias, ptr, layout.size(), TrollDetail::Aliased { owner: owner as usize });
+        Some(ptr)
+    }
+
+    /// Find the alias at `ptr`, if it is one.
+    unsafe fn find_alias(&self, ptr: *const u8) -> Option<Alias> {
+        if !TROLLING_ON {
+            return None;
+        }
+
+        (*self.get_metadata()).aliases.iter().copied().find(|alias| core::ptr::eq(alias.ptr, ptr))
+    }
+
+    /// Forget the alias at `ptr`, if it is one. Returns whether it was.
+    unsafe fn unalias(&self, ptr: *mut u8) -> bool {
+        if !TROLLING_ON {
+            return false;
+        }
+
+        match (*self.get_metadata()).aliases.iter_mut().find(|alias| alias.ptr == ptr) {
+            Some(alias) => {
+                *alias = Alias::NONE;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Take a while over the block at `ptr`, sometimes.
+    unsafe fn stall(&self, ptr: *mut u8, entropy: u64) {
+        let troller = &mut *self.troller.get();
+        if !troller.roll(entropy, TrollAction::Stall) {
+            return;
+        }
+
+        let micros = 1 + troller.next(entropy) % troller.policy.stall.max(1);
+        #[cfg(feature = "std")]
+        std::thread::sleep(core::time::Duration::from_micros(micros));
+        #[cfg(not(feature = "std"))]
+        for _ in 0..micros * SPINS_PER_MICRO {
+            core::hint::spin_loop();
+        }
+
+        let bucket = (micros.ilog2() as usize).min(STALL_BUCKETS - 1);
+        self.record(|counters| {
+            counters.stalls[bucket] += 1;
+            counters.trolls += 1;
+        });
+        troller.log(TrollAction::Stall, ptr, self.usable_size(ptr), TrollDetail::Stalled { micros });
+    }
+
+    /// Do a `realloc` the lazy way, picking how by `pick`. Returns whatever the owner gets to
+    /// live with.
+    unsafe fn realloc_lazily(&self, ptr: *mut u8, layout: Layout, new_size: usize, pick: u64) -> *mut u8 {
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+        let usable_size = self.usable_size(ptr);
+        let kept = layout.size().min(new_size);
+
+        let (new_ptr, detail) = match pick % 3 {
+            // Only lazy if it actually had to grow
+            0 if new_size > usable_size => (ptr, TrollDetail::NotGrown { wanted: new_size }),
+            1 if kept > 0 => {
+                let new_ptr = self.alloc_block(new_layout, false, Some(ptr));
+                if new_ptr.is_null() {
+                    return new_ptr;
+                }
+
+                // Less than all of it, possibly nothing
+                let copied = (pick / 3) as usize % kept;
+                core::ptr::copy_nonoverlapping(ptr, new_ptr, copied);
+                self.dealloc(ptr, layout);
+                (new_ptr, TrollDetail::PartialCopy { copied })
+            }
+            _ => {
+                let new_ptr = self.alloc_block(new_layout, false, Some(ptr));
+                if new_ptr.is_null() {
+                    return new_ptr;
+                }
+
+                // All of it, but the old one stays behind for the leak report, owned up to as trolled
+                core::ptr::copy_nonoverlapping(ptr, new_ptr, kept);
+                if !self.unalias(ptr) {
+                    let block = Self::payload_to_block(ptr as usize);
+                    Self::set_block(block, Self::block_size(block), Self::block_flags(block) | TROLLED_BIT);
+                }
+                (new_ptr, TrollDetail::OldKept { old: ptr as usize })
+            }
+        };
+
+        self.record(|counters| counters.trolls += 1);
+        (*self.troller.get()).log(TrollAction::LazyRealloc, new_ptr, self.usable_size(new_ptr), detail);
+        self.debug_check();
+        new_ptr
+    }
+
     /// Get a block with a given malloc index.
     unsafe fn get_block_by_index(&self, index: usize) -> *mut u8 {
         // Just iterate until a certain malloced block index
         let mut curr_block_ptr: BlockPointer = Self::as_block_ptr(self.heap_start());
         let mut curr_block_index: usize = 0;
 
-        // Go until at correct index
-        while curr_block_index < index {
+        // Go until at correct index, and at an alloced block: the one after the last one counted
+        // can just as well be free, like the padding alignment leaves in between
+        while curr_block_index < index || Self::is_free(curr_block_ptr) {
             if !Self::is_free(curr_block_ptr) {
                 // Alloced block, index can be incremented
                 curr_block_index += 1;
@@ -306,110 +1294,251 @@ impl Trollocator {
 
         Self::block_to_payload(curr_block_ptr)
     }
-}
 
-impl TrollocatorMetadata {
-    /// Reinterpret the beginning of the heap as a metadata struct.
-    const fn from(heap: *mut u8) -> *mut Self {
-        heap as *mut TrollocatorMetadata
-    }
-}
-
-unsafe impl GlobalAlloc for Trollocator {
-    /// Allocate a block based on the given layout. Absolutely no funny business here.
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+    /// Allocate a block based on the given layout, possibly one that comes up short if
+    /// `shortchange`. Which it never is for allocations the allocator is about to write all of
+    /// itself, like zeroed ones or ones `realloc` copies into, or it would be the one scribbling
+    /// over the footer and the next header.
+    /// 
+    /// For the same reason those never get trolled on the way out, and neither does `spare`, the
+    /// block `realloc` is about to copy out of and free.
+    unsafe fn alloc_block(&self, layout: Layout, shortchange: bool, spare: Option<*mut u8>) -> *mut u8 {
         // Use ASLR as a seed for randomness. Thanks Ojas!
         let _stack_marker: u8 = 0b01010101;
+        // No inline heap and no region to use instead, nothing to give out.
+        if self.heap_base().is_null() {
+            return core::ptr::null_mut();
+        }
+
         // This is illegal. I do not even care. No one can stop me. Not even the fed. I have no remorse either. I will do it again.
-        let metadata = TrollocatorMetadata::from(self.heap.get().cast::<u8>());
+        let metadata = self.get_metadata();
         if !(*metadata).initialized {
             // Initialize in alloc because I can??? Lol??? what will you actually do about it? Nothing. Grow up.
-            (*metadata).heap_size = MAX_HEAP_SIZE;
-            (*metadata).heap_start = self.heap.get().cast::<u8>().wrapping_add(core::mem::size_of::<TrollocatorMetadata>());
-
-            // Make the heap one big block.
-            let block = Self::as_block_ptr((*metadata).heap_start as usize);
-            (*block).header = BlockHeader { size: MAX_HEAP_SIZE - HEADER_SIZE, prev: core::ptr::null_mut(), free: true };
-            (*block).free_node = FreeNode { prev: core::ptr::null_mut(), next: core::ptr::null_mut() };
-
-            (*metadata).next_free = (*metadata).heap_start;
-            (*metadata).free_list_head = block;
-            (*metadata).num_alloced_blocks = 0;
-            (*metadata).initialized = true;
+            Self::init_heap(self.heap.get().cast::<u8>(), N);
         }
 
         // Align layout to block size
         let actual_layout = Self::align(layout);
-        let req_size = actual_layout.0;
+        let mut req_size = actual_layout.0;
+        let req_align = actual_layout.1;
+
+        // Come up short, or crooked, on purpose. Not both, that is just mean.
+        let entropy = (&_stack_marker as *const u8 as u64) ^ (layout.size() as u64);
+        let undersize = TROLLING_ON && shortchange && (*self.troller.get()).roll(entropy, TrollAction::Undersize);
+        // Nothing to misalign for byte aligned requests
+        let misalign = TROLLING_ON && !undersize && layout.align() > 1 && (*self.troller.get()).roll(entropy, TrollAction::Misalign);
+
+        if undersize {
+            // Rounding down, since rounding up might not be short at all
+            let short_size = layout.size().saturating_sub((*self.troller.get()).policy.undersize.max(1)) / ALIGNMENT * ALIGNMENT;
+            req_size = req_size.min(short_size.max(mem::size_of::<FreeNode<Block>>()));
+        }
 
+        if misalign {
+            // Room to be pushed off by less than a word
+            req_size += ALIGNMENT;
+        }
+
+        // Or not even a block of its own
+        if TROLLING_ON && !undersize && !misalign && (*self.troller.get()).roll(entropy, TrollAction::Alias) {
+            if let Some(alias) = self.alias(layout, entropy) {
+                self.debug_check();
+                return alias;
+            }
+        }
 
         // Actually allocate
-        if let Some(fitting_block) = self.search_free_list(req_size) {
-            // Split block if possible
-            if ((*fitting_block).header.size - req_size) >= MIN_BLOCK_SIZE {
-                let original_size = (*fitting_block).header.size;
-                // Can split this block: This block is now clamped down to request size,
-                // remaining size is used for next block
-                (*fitting_block).header.size = req_size;
-
-                // Create a new block at the address after the size of the malloced block 
-                // (offset by the header size of the malloced block itself)
-                *(((fitting_block as usize) + req_size + HEADER_SIZE) as BlockPointer) = Block {
-                    header: BlockHeader { size: original_size - (req_size + HEADER_SIZE), prev: fitting_block, free: true },
-                    free_node: FreeNode { prev: core::ptr::null_mut(), next: core::ptr::null_mut() }
-                };
+        if let Some(fitting_block) = self.search_free_list(req_size, req_align) {
+            // Skip ahead to an aligned payload if necessary
+            let fitting_block = self.align_block(fitting_block, req_align);
+            self.record(|counters| counters.free_block_removed(Self::block_size(fitting_block)));
 
-                // Add this new block to the free list
-                self.free_list_add(((fitting_block as usize) + req_size + HEADER_SIZE) as BlockPointer);
-            }
+            // Split block if possible
+            self.split_block(fitting_block, req_size);
 
             // Remove this block from the free list
             self.free_list_remove(fitting_block);
 
             // Mark block allocated
-            (*fitting_block).header.free = false;
+            Self::set_block(fitting_block, Self::block_size(fitting_block), 0);
+            #[cfg(feature = "alloc")]
+            {
+                (*fitting_block).header.seq = (*metadata).num_allocations;
+            }
+            (*metadata).num_allocations += 1;
 
-            let block_address = Self::block_to_payload(fitting_block);
+            let mut block_address = Self::block_to_payload(fitting_block);
             
             (*self.get_metadata()).num_alloced_blocks += 1;
+            self.record(|counters| counters.allocated(Self::block_size(fitting_block)));
+
+            // Only a troll if the block did not end up big enough anyway, like when it was too small to split
+            let usable_size = Self::block_size(fitting_block);
+            if undersize && usable_size < layout.size() {
+                self.record(|counters| counters.trolls += 1);
+                (*self.troller.get()).log(TrollAction::Undersize, block_address, usable_size, TrollDetail::Undersized { by: layout.size() - usable_size });
+            }
+
+            if misalign {
+                // Off by a byte, or by half the alignment. Never by a whole word, so the block can still be found.
+                let troller = &mut *self.troller.get();
+                let offset = if troller.next(entropy) & 1 == 0 { 1 } else { layout.align().min(ALIGNMENT) / 2 };
+                block_address = block_address.add(offset);
+
+                self.record(|counters| counters.trolls += 1);
+                troller.log(TrollAction::Misalign, block_address, usable_size - offset, TrollDetail::Misaligned { offset });
+            }
 
             // Trolling.
             if TROLLING_ON { 
-                // Feeding a stack marker address (randomized by ASLR) and block address into wyrand as a seed and using this as the basis of randomness.
-                let rand_result: usize = wyrand((&_stack_marker as *const u8 as u64) ^ (block_address as *const u8 as u64)) as usize;
-                let randex: usize = rand_result  % (*self.get_metadata()).num_alloced_blocks;
-                let rand_bit: usize = (rand_result % (core::mem::size_of::<usize>() * 8)).checked_sub(1).unwrap_or(0);
-                if ((randex & (1 << rand_bit)) >> rand_bit) == 1 {
-                    let rand_block = self.get_block_by_index(randex);
-                    // Get owned. You're owned. Trolled. You're trolled. You're owned and trolled.
-                    self.dealloc(rand_block, layout);
+                self.tick(Fuse::Allocs);
+
+                // Feeding a stack marker address (randomized by ASLR) and block address into wyrand as a seed and using this as the basis of randomness,
+                // unless the policy says to use its seed instead.
+                let entropy = (&_stack_marker as *const u8 as u64) ^ (block_address as *const u8 as u64);
+                let troller = &mut *self.troller.get();
+
+                // Hands off whatever the caller is about to write or free itself. Aliases are not
+                // blocks, there is nothing to keep hands off of.
+                let spared = [
+                    (!shortchange).then_some(fitting_block),
+                    spare.filter(|&ptr| self.find_alias(ptr).is_none()).map(|ptr| Self::payload_to_block(ptr as usize)),
+                ];
+                let live = (*self.get_metadata()).num_alloced_blocks.saturating_sub(spared.iter().flatten().count());
+                let picked = troller.victim(entropy, live).and_then(|randex| {
+                    self.physical_blocks()
+                        .filter(|&block| !Self::is_free(block) && !spared.contains(&Some(block)))
+                        .nth(randex)
+                });
+
+                if let Some(picked) = picked {
+                    let rand_block = Self::block_to_payload(picked);
+                    let rand_size = Self::block_size(Self::payload_to_block(rand_block as usize));
+                    let action = troller.action(entropy);
+
+                    let trolled = match action {
+                        TrollAction::Free => {
+                            // Get owned. You're owned. Trolled. You're trolled. You're owned and trolled.
+                            self.release(rand_block, true);
+                            troller.log(action, rand_block, rand_size, TrollDetail::None);
+                            true
+                        }
+                        TrollAction::BitFlip => {
+                            // Anywhere in the payload, which stops well short of the footer
+                            for _ in 0..=troller.next(entropy) % MAX_BIT_FLIPS {
+                                let bit = troller.next(entropy) as usize % (rand_size * 8);
+                                *rand_block.add(bit / 8) ^= 1 << (bit % 8);
+                                troller.log(action, rand_block, rand_size, TrollDetail::BitFlip { offset: bit / 8, bit: (bit % 8) as u8 });
+                            }
+                            true
+                        }
+                        TrollAction::TimeBomb => {
+                            let after = 1 + (troller.next(entropy) % MAX_FUSE) as usize;
+                            let fuse = if troller.next(entropy) & 1 == 0 { Fuse::Allocs } else { Fuse::Deallocs };
+
+                            // No room for another bomb, or already one there, no troll
+                            let armed = self.arm(rand_block, after, fuse);
+                            if armed {
+                                troller.log(action, rand_block, rand_size, TrollDetail::Armed { after, fuse });
+                            }
+                            armed
+                        }
+                        TrollAction::Swap => {
+                            // Only with a block of the same size, so that nothing spills over
+                            let victim = Self::payload_to_block(rand_block as usize);
+                            let others = self
+                                .physical_blocks()
+                                .filter(|&block| block != victim && !Self::is_free(block) && Self::block_size(block) == rand_size);
+                            let count = others.clone().count();
+
+                            match others.clone().nth(troller.next(entropy) as usize % count.max(1)) {
+                                Some(other) => {
+                                    let other = Self::block_to_payload(other);
+                                    core::ptr::swap_nonoverlapping(rand_block, other, rand_size);
+                                    troller.log(action, rand_block, rand_size, TrollDetail::Swapped { with: other as usize });
+                                    true
+                                }
+                                // Nobody to swap with
+                                None => false,
+                            }
+                        }
+                        // Never picked for a victim
+                        TrollAction::Undersize | TrollAction::Misalign | TrollAction::LazyRealloc | TrollAction::Unzeroed | TrollAction::Alias | TrollAction::Leak | TrollAction::Stall => false,
+                    };
+
+                    if trolled {
+                        self.record(|counters| counters.trolls += 1);
+                    }
                 }
+
+                // Take our time handing it out
+                self.stall(block_address, entropy);
             }
 
+            self.debug_check();
+
             // Return the malloced block
-            return block_address;
+            block_address
         } else {
-            return core::ptr::null_mut();
+            core::ptr::null_mut()
         }
     }
+}
+
+impl<const N: usize> Default for Trollocator<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TrollocatorMetadata {
+    /// Reinterpret the beginning of the heap as a metadata struct.
+    const fn from(heap: *mut u8) -> *mut Self {
+        heap as *mut TrollocatorMetadata
+    }
+}
+
+unsafe impl<const N: usize> GlobalAlloc for Trollocator<N> {
+    /// Allocate a block based on the given layout. Absolutely no funny business here.
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        self.alloc_block(layout, true, None)
+    }
 
     /// Free a block previously allocated with [`alloc`].
-    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
         // Notice that I do not care what layout you requested. It is meaningless to me. Like an ant. Like a little menial ant.
 
-        // First move back to block pointer
-        let block = Self::payload_to_block(ptr as usize);
-
-        // Mark block as free
-        (*block).header.free = true;
+        // I will get to it when I get to it
+        if TROLLING_ON {
+            self.stall(ptr, ptr as u64);
+        }
 
-        (*self.get_metadata()).num_alloced_blocks = (*self.get_metadata()).num_alloced_blocks.checked_sub(1).unwrap_or(0);
+        // Sometimes I do not care that you asked at all. Nobody will notice until the heap runs out.
+        if TROLLING_ON && (*self.troller.get()).roll(ptr as u64, TrollAction::Leak) {
+            self.record(|counters| {
+                counters.swallowed_frees += 1;
+                counters.trolls += 1;
+            });
+            (*self.troller.get()).log(TrollAction::Leak, ptr, self.usable_size(ptr), TrollDetail::None);
+
+            // The alias slot goes back all the same, only real blocks get to stick around
+            if !self.unalias(ptr) {
+                let block = Self::payload_to_block(ptr as usize);
+                Self::set_block(block, Self::block_size(block), Self::block_flags(block) | TROLLED_BIT);
+            }
+        } else {
+            self.record(|counters| counters.frees += 1);
+            // Aliases were never blocks of their own, there is nothing to give back
+            if !self.unalias(ptr) {
+                self.release(ptr, false);
+            }
+        }
 
-        // Now add to free list
-        self.free_list_add(block);
+        if TROLLING_ON {
+            self.tick(Fuse::Deallocs);
+        }
 
-        // Coalesce this block
-        self.coalesce(block);
+        self.debug_check();
     }
 
     // I let the functions below just get auto-generated by VS Code.
@@ -418,8 +1547,35 @@ unsafe impl GlobalAlloc for Trollocator {
     unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
         let size = layout.size();
         // SAFETY: the safety contract for `alloc` must be upheld by the caller. it will not be.
-        let ptr = unsafe { self.alloc(layout) };
-        if !ptr.is_null() {
+        let ptr = unsafe { self.alloc_block(layout, false, None) };
+        if ptr.is_null() {
+            return ptr;
+        }
+
+        let troller = &mut *self.troller.get();
+        let entropy = ptr as u64 ^ size as u64;
+        if TROLLING_ON && size > 0 && troller.roll(entropy, TrollAction::Unzeroed) {
+            // Zeroes everywhere but one stretch, which gets to keep what was there
+            let offset = troller.next(entropy) as usize % size;
+            let len = 1 + troller.next(entropy) as usize % (size - offset);
+            // SAFETY: no
+            unsafe {
+                core::ptr::write_bytes(ptr, 0, offset);
+                core::ptr::write_bytes(ptr.add(offset + len), 0, size - offset - len);
+            }
+
+            // Fresh memory has nothing stale in it, so make something up
+            let garbage = troller.next(entropy).to_le_bytes();
+            for index in 0..len {
+                let byte = ptr.add(offset + index);
+                if *byte == 0 {
+                    *byte = garbage[index % garbage.len()] | 1;
+                }
+            }
+
+            self.record(|counters| counters.trolls += 1);
+            troller.log(TrollAction::Unzeroed, ptr, self.usable_size(ptr), TrollDetail::Stale { offset, len });
+        } else {
             // SAFETY: no
             unsafe { core::ptr::write_bytes(ptr, 0, size) };
         }
@@ -427,12 +1583,37 @@ unsafe impl GlobalAlloc for Trollocator {
     }
 
     /// Reallocate a block. No guarantee this will work.
+    /// 
+    /// Shrinks in place, and grows in place when the next physical block is free and big
+    /// enough. Only otherwise does it move the block, which also gives trolling a chance to run.
     unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
         // SAFETY: the caller must ensure that the `new_size` does not overflow.
         // `layout.align()` comes from a `Layout` and is thus guaranteed to be valid.
         let new_layout = unsafe { Layout::from_size_align_unchecked(new_size, layout.align()) };
+
+        self.record(|counters| counters.reallocs += 1);
+
+        // Can't be bothered, sometimes
+        if TROLLING_ON {
+            let troller = &mut *self.troller.get();
+            let entropy = ptr as u64 ^ new_size as u64;
+            if troller.roll(entropy, TrollAction::LazyRealloc) {
+                let pick = troller.next(entropy);
+                return self.realloc_lazily(ptr, layout, new_size, pick);
+            }
+        }
+
+        // Try to avoid moving at all, keeping room for however far trolling misaligned the block.
+        // Aliases always move, resizing them in place would resize whatever they alias.
+        let offset = ptr as usize % ALIGNMENT;
+        let req_size = Self::align(Layout::from_size_align_unchecked(new_size + offset, layout.align())).0;
+        if self.find_alias(ptr).is_none() && self.resize_in_place(Self::payload_to_block(ptr as usize), req_size) {
+            self.debug_check();
+            return ptr;
+        }
+
         // SAFETY: the caller must ensure that `new_layout` is greater than zero. if they don't, I do not care.
-        let new_ptr = unsafe { self.alloc(new_layout) };
+        let new_ptr = unsafe { self.alloc_block(new_layout, false, Some(ptr)) };
         if !new_ptr.is_null() {
             // SAFETY: the previously allocated block cannot overlap the newly allocated block. it might though. your problem now.
             unsafe {
@@ -442,4 +1623,67 @@ unsafe impl GlobalAlloc for Trollocator {
         }
         new_ptr
     }
-}
\ No newline at end of file
+}
+#[cfg(feature = "nightly")]
+impl<const N: usize> Trollocator<N> {
+    /// Move a block between layouts for the [`Allocator`](Allocator) methods, which
+    /// unlike [`GlobalAlloc::realloc`] are allowed to change the alignment.
+    unsafe fn reallocate(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let new_ptr = if new_layout.align() <= old_layout.align() {
+            // Anything aligned for the old layout is aligned for the new one, so realloc can have it
+            self.realloc(ptr.as_ptr(), old_layout, new_layout.size())
+        } else {
+            // Stricter alignment, have to move
+            let new_ptr = self.alloc_block(new_layout, false, Some(ptr.as_ptr()));
+            if !new_ptr.is_null() {
+                core::ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr, old_layout.size().min(new_layout.size()));
+                self.dealloc(ptr.as_ptr(), old_layout);
+            }
+            new_ptr
+        };
+
+        let new_ptr = NonNull::new(new_ptr).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(new_ptr, new_layout.size()))
+    }
+}
+
+#[cfg(feature = "nightly")]
+/// Lets a single collection be trolled without replacing the global allocator, e.g. with
+/// `Vec::new_in(&TROLLOCATOR)`.
+unsafe impl<const N: usize> Allocator for Trollocator<N> {
+    fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = unsafe { self.alloc(layout) };
+        let ptr = NonNull::new(ptr).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let ptr = unsafe { self.alloc_zeroed(layout) };
+        let ptr = NonNull::new(ptr).ok_or(AllocError)?;
+        Ok(NonNull::slice_from_raw_parts(ptr, layout.size()))
+    }
+
+    unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+        self.dealloc(ptr.as_ptr(), layout);
+    }
+
+    unsafe fn grow(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.reallocate(ptr, old_layout, new_layout)
+    }
+
+    unsafe fn grow_zeroed(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let new_block = self.reallocate(ptr, old_layout, new_layout)?;
+
+        // Only the part that did not exist before needs zeroing, and only as far as it actually
+        // exists: a lazy realloc hands back the old block, ungrown
+        let new_ptr = new_block.cast::<u8>().as_ptr();
+        let grown = new_layout.size().min(self.usable_size(new_ptr));
+        core::ptr::write_bytes(new_ptr.add(old_layout.size()), 0, grown.saturating_sub(old_layout.size()));
+
+        Ok(new_block)
+    }
+
+    unsafe fn shrink(&self, ptr: NonNull<u8>, old_layout: Layout, new_layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        self.reallocate(ptr, old_layout, new_layout)
+    }
+}
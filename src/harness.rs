@@ -0,0 +1,208 @@
+//! # Harness
+//!
+//! What [`#[trolloc::test]`](crate::test) expands to: run a test once per seed, each run in a
+//! process of its own, and report the seeds that fell over along with what got trolled.
+//!
+//! A run that fell over might have done so with a segfault rather than a panic, which is the
+//! whole point of trolling, so the only safe place to run it is somewhere else. The test binary
+//! runs itself again, filtered down to the one test, with [`TRIAL`] set to tell it that it is
+//! the run and not the harness.
+//!
+//! Only what the test allocates from the [`Trollocator`] it is handed gets trolled: the global
+//! allocator is not replaced, both because the harness itself needs one that works and because
+//! a test binary only gets one. Ordinary `Vec`s and `Box`es in the test are never trolled.
+//!
+//! Every run gets a fresh [`Trollocator`] over a heap of its own, with its seed and whatever else
+//! the [`env`](crate::policy::env) variables say: `TROLLOC_PROBABILITY`, `TROLLOC_ACTIONS`,
+//! `TROLLOC_HEAP_SIZE` and the rest work like they do for `trolloc-preload`. Setting `TROLLOC_SEED` while
+//! running the tests replays only that seed, which is what to do once a report comes in.
+
+use std::{
+    collections::hash_map::RandomState,
+    env,
+    fs::{self, File, OpenOptions},
+    hash::{BuildHasher, Hasher},
+    io::{ErrorKind, Write},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process::{self, Command},
+    string::{String, ToString},
+    sync::Mutex,
+    vec::Vec,
+};
+
+use crate::{
+    gjallocator::Trollocator,
+    policy::{env as vars, TrollEvent, TrollPolicy},
+};
+
+/// Set in the environment of a run, so that it knows to run the test instead of spawning more
+/// runs.
+pub const TRIAL: &str = "TROLLOC_TRIAL";
+
+/// Heap size for runs, 1 MiB, unless `TROLLOC_HEAP_SIZE` says otherwise.
+const HEAP_SIZE: usize = 1 << 20;
+
+/// How many of the last trolls to show for a failed seed. The ones that did the damage tend to
+/// be near the end.
+const REPORTED_TROLLS: usize = 10;
+
+/// Exit code of a run that panicked, the same as a failed test.
+const PANICKED: i32 = 101;
+
+/// The troll log of the current run.
+static LOG: Mutex<Option<File>> = Mutex::new(None);
+
+#[derive(Clone, Copy, Debug)]
+/// A test to run many times over.
+pub struct Trial {
+    /// Path to the test, starting with the crate name, as [`module_path`] has it.
+    pub name: &'static str,
+    /// Seed of the first run, the rest count up from it.
+    pub seed: u64,
+    /// How many runs.
+    pub runs: usize,
+}
+
+impl Trial {
+    /// Get the name of the test as the test harness has it, without the crate name.
+    fn test_name(&self) -> &'static str {
+        self.name.split_once("::").map_or(self.name, |(_, name)| name)
+    }
+}
+
+/// A run that fell over.
+struct Failure {
+    seed: u64,
+    /// How it fell over, e.g. `exit status: 101` or `signal: 11 (SIGSEGV)`.
+    status: String,
+    /// What it said on the way down.
+    stderr: String,
+    /// The last few trolls before it did.
+    trolls: Vec<String>,
+}
+
+/// Run `test` for every seed in `trial`, and panic with a report if any of them fell over.
+pub fn run(trial: Trial, test: fn(&Trollocator<0>)) {
+    if env::var_os(TRIAL).is_some() {
+        run_one(test);
+    }
+
+    // A seed from the environment replays just that seed
+    let seeds: Vec<u64> = match env::var(vars::SEED).ok().and_then(|seed| seed.parse().ok()) {
+        Some(seed) => vec![seed],
+        None => (0..trial.runs as u64).map(|run| trial.seed.wrapping_add(run)).collect(),
+    };
+
+    let failures: Vec<Failure> = seeds.iter().filter_map(|&seed| spawn(&trial, seed)).collect();
+    if failures.is_empty() {
+        return;
+    }
+
+    let mut report = format!("{} of {} runs failed\n", failures.len(), seeds.len());
+    for failure in &failures {
+        report += &format!("\nseed {}: {}\n", failure.seed, failure.status);
+        for line in failure.stderr.lines().filter(|line| !line.trim().is_empty()) {
+            report += &format!("    {line}\n");
+        }
+        report += &format!("  last {} trolls:\n", failure.trolls.len());
+        for troll in &failure.trolls {
+            report += &format!("    {troll}\n");
+        }
+    }
+    report += &format!("\nreplay a seed with {}=<seed>", vars::SEED);
+
+    panic!("{report}");
+}
+
+/// Run the test with `seed` in a process of its own. Returns how it fell over, if it did.
+fn spawn(trial: &Trial, seed: u64) -> Option<Failure> {
+    let log = temp_log();
+
+    let output = Command::new(env::current_exe().expect("no test binary to run"))
+        .args([trial.test_name(), "--exact", "--nocapture", "--include-ignored", "--test-threads=1"])
+        .env(TRIAL, "1")
+        .env(vars::SEED, seed.to_string())
+        .env(vars::LOG, &log)
+        .output()
+        .expect("could not run the test binary");
+
+    let trolls = read_log(&log);
+    let _ = fs::remove_file(&log);
+
+    if output.status.success() {
+        return None;
+    }
+
+    Some(Failure {
+        seed,
+        status: output.status.to_string(),
+        stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+        trolls,
+    })
+}
+
+/// Make a fresh, empty troll log in the temp directory, for nobody but us. Its name is random
+/// and it is never one that was there already, so nobody can have put a symlink there first.
+fn temp_log() -> PathBuf {
+    for _ in 0..16 {
+        // Hashers are randomly keyed, which is all the randomness std hands out
+        let name = format!("trolloc-{}-{:016x}.log", process::id(), RandomState::new().build_hasher().finish());
+        let path = env::temp_dir().join(name);
+
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+        match options.open(&path) {
+            Ok(_) => return path,
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => panic!("could not make a troll log: {err}"),
+        }
+    }
+
+    panic!("could not make a troll log, the temp directory is full of them");
+}
+
+/// Get the last few lines of a troll log.
+fn read_log(log: &Path) -> Vec<String> {
+    let log = fs::read_to_string(log).unwrap_or_default();
+    let lines: Vec<String> = log.lines().map(str::to_string).collect();
+    lines[lines.len().saturating_sub(REPORTED_TROLLS)..].to_vec()
+}
+
+/// Be the run: troll `test` by the policy in the environment, and exit with how it went.
+fn run_one(test: fn(&Trollocator<0>)) -> ! {
+    let var = |name: &str| env::var(name).ok();
+    let environment: Vec<(String, String)> = env::vars().collect();
+    let policy = TrollPolicy::from_env(|name| {
+        environment.iter().find(|(key, _)| key == name).map(|(_, value)| value.as_str())
+    })
+    .expect("bad trolling policy in the environment");
+
+    let heap_size = var(vars::HEAP_SIZE).and_then(|size| size.parse().ok()).unwrap_or(HEAP_SIZE);
+    // The harness made it already, so only ever open that one
+    if let Some(path) = var(vars::LOG) {
+        *LOG.lock().unwrap() = OpenOptions::new().append(true).open(path).ok();
+    }
+
+    // The heap comes from the real allocator, and is never given back since the process is about
+    // to exit anyway
+    let heap = vec![0u8; heap_size].leak();
+    let allocator = Trollocator::<0>::new().with_policy(TrollPolicy { log: Some(log), ..policy });
+    // SAFETY: The heap is ours alone, and nothing has been allocated yet
+    assert!(unsafe { allocator.init(heap.as_mut_ptr(), heap_size) }, "heap too small to run in");
+
+    // The panic message has already gone to stderr by the time this returns
+    let panicked = panic::catch_unwind(AssertUnwindSafe(|| test(&allocator))).is_err();
+    process::exit(if panicked { PANICKED } else { 0 });
+}
+
+/// Append a troll to the log. The global allocator is not the one being trolled, so this can
+/// allocate all it likes.
+fn log(event: &TrollEvent) {
+    if let Some(file) = LOG.lock().unwrap().as_mut() {
+        let _ = writeln!(file, "{event}");
+    }
+}
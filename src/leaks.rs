@@ -0,0 +1,137 @@
+//! # Leaks
+//!
+//! The writeup proves that trolloc never leaks. This checks.
+//!
+//! Every block lives in the [`gjallocator`](crate::gjallocator) heap, so whatever is still
+//! allocated when a program is done is exactly what it leaked.
+//! [`Trollocator::leak_report`](crate::gjallocator::Trollocator::leak_report) lists those, along
+//! with the blocks trolling freed before their owners were done with them, which are not leaks
+//! even though their owners never gave them back. Neither are the blocks whose frees trolling
+//! swallowed: those stay allocated, but their owners did give them back, so they count as trolled.
+//!
+//! Blocks are told apart by their allocation sequence number: the first allocation from a heap
+//! is #0, the next one is #1, and so on. A `GlobalAlloc` is never told who is calling it, so the
+//! number is all there is to go on. Run the program again with the same seed and break on that
+//! allocation to find out where it came from.
+//!
+//! With the `std` feature,
+//! [`Trollocator::report_leaks_at_exit`](crate::gjallocator::Trollocator::report_leaks_at_exit)
+//! prints the report to stderr when the program exits.
+
+use alloc::vec::Vec;
+use core::fmt;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// A block nobody gave back.
+pub struct Leak {
+    /// Address of the payload, as it was handed out.
+    pub address: usize,
+    /// Leasable size of the block.
+    pub size: usize,
+    /// Allocation sequence number. For trolled blocks, this is the allocation trolling freed, as
+    /// far as is known: it gets muddled when trolled blocks are merged with their neighbours.
+    pub seq: usize,
+    /// Whether trolling freed the block, or swallowed its owner's free. Otherwise, it genuinely
+    /// leaked.
+    pub trolled: bool,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+/// Every block nobody gave back, in address order.
+pub struct LeakReport {
+    /// Leaked and trolled blocks alike.
+    pub leaks: Vec<Leak>,
+}
+
+impl LeakReport {
+    /// Check whether nothing leaked. Trolled blocks do not count.
+    pub fn is_empty(&self) -> bool {
+        self.leaked().next().is_none()
+    }
+
+    /// Iterate over the blocks that genuinely leaked.
+    pub fn leaked(&self) -> impl Iterator<Item = &Leak> + Clone {
+        self.leaks.iter().filter(|leak| !leak.trolled)
+    }
+
+    /// Iterate over the blocks that trolling freed, or kept.
+    pub fn trolled(&self) -> impl Iterator<Item = &Leak> + Clone {
+        self.leaks.iter().filter(|leak| leak.trolled)
+    }
+}
+
+impl fmt::Display for LeakReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write_report(f, self.leaks.iter().copied())
+    }
+}
+
+/// Write out a leak report, the leaks first and then the trolled blocks.
+pub(crate) fn write_report(out: &mut impl fmt::Write, leaks: impl Iterator<Item = Leak> + Clone) -> fmt::Result {
+    for (trolled, what) in [(false, "leaked"), (true, "trolled, freed too early or not at all")] {
+        let blocks = leaks.clone().filter(|leak| leak.trolled == trolled);
+        let (count, bytes) = blocks.clone().fold((0, 0), |(count, bytes), leak| (count + 1, bytes + leak.size));
+        writeln!(out, "{count} blocks {what}, {bytes} bytes")?;
+
+        for leak in blocks {
+            writeln!(out, "  #{}: {} bytes at {:#x}", leak.seq, leak.size, leak.address)?;
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(feature = "std")]
+pub(crate) use at_exit::report_at_exit;
+
+#[cfg(feature = "std")]
+mod at_exit {
+    use core::{
+        ffi::c_int,
+        fmt,
+        ptr,
+        sync::atomic::{AtomicPtr, Ordering},
+    };
+    use std::io::{self, Write};
+
+    use crate::gjallocator::Trollocator;
+
+    extern "C" {
+        fn atexit(callback: extern "C" fn()) -> c_int;
+    }
+
+    /// The allocator to report on, type erased since it could have any heap size.
+    static ALLOCATOR: AtomicPtr<()> = AtomicPtr::new(ptr::null_mut());
+
+    /// Report on the leaks of `allocator` when the program exits. Only the first allocator to
+    /// ask gets reported on. Returns whether that was this one.
+    pub(crate) fn report_at_exit<const N: usize>(allocator: &'static Trollocator<N>) -> bool {
+        let erased = allocator as *const Trollocator<N> as *mut ();
+        if ALLOCATOR.compare_exchange(ptr::null_mut(), erased, Ordering::AcqRel, Ordering::Acquire).is_err() {
+            return false;
+        }
+
+        // SAFETY: The callback only looks at the allocator it was registered for, which lives forever
+        unsafe { atexit(report::<N>) == 0 }
+    }
+
+    /// Print the report, without allocating: the allocator might be the global one, and it is
+    /// rude to leak while reporting leaks.
+    extern "C" fn report<const N: usize>() {
+        // SAFETY: Only `report_at_exit::<N>` could have put it there
+        let allocator = unsafe { &*(ALLOCATOR.load(Ordering::Acquire) as *const Trollocator<N>) };
+
+        let mut stderr = Stderr(io::stderr().lock());
+        let _ = writeln!(stderr.0, "trolloc: leak report");
+        let _ = super::write_report(&mut stderr, allocator.leaks());
+    }
+
+    /// Stderr, for `write!`ing formatted text to without going through a `String`.
+    struct Stderr(io::StderrLock<'static>);
+
+    impl fmt::Write for Stderr {
+        fn write_str(&mut self, s: &str) -> fmt::Result {
+            self.0.write_all(s.as_bytes()).map_err(|_| fmt::Error)
+        }
+    }
+}
@@ -18,26 +18,69 @@
 //! satisfy the requirements for Rust's [`GlobalAlloc`](core::alloc::GlobalAlloc) trait, which
 //! allows an allocator to be directly used by a safe Rust program. For the most up-to-date,
 //! correct implementation, refer to [`gjallocator`](crate::gjallocator).
+//! 
+//! To troll programs that need a production-quality heap, [`troll`](crate::troll) wraps any
+//! other [`GlobalAlloc`](core::alloc::GlobalAlloc) and trolls on top of it instead.
+//! 
+//! How often to troll, and how, is up to a [`policy`](crate::policy).
+//! 
+//! To troll C and C++ programs as well, the `trolloc-preload` crate in this workspace builds a
+//! shared library exporting `malloc`, `free` and friends over the gjallocator, to be loaded
+//! with `LD_PRELOAD`.
+//! 
+//! ## Features
+//! 
+//! - `std` (default): printing and reporting, such as
+//!   [`print_heap`](crate::gjallocator::Trollocator::print_heap), and
+//!   [`#[trolloc::test]`](crate::test) for running tests under many seeds. Turn off default
+//!   features to build the core heap on `no_std`.
+//! - `alloc`: reporting that needs to allocate, such as heap [`snapshot`](crate::snapshot)s and
+//!   [`leaks`](crate::leaks) reports.
+//!   Implied by `std`.
+//! - `trolling`: actually troll. Off by default, so that the allocators are merely bad unless
+//!   you ask for more.
+//! - `stats`: keep allocation statistics.
+//! - `debug-checks`: check the heap for corruption after every call, and panic when it finds
+//!   any. Combined with `trolling`, this finds out very quickly.
+//! - `nightly`: the unstable [`Allocator`](core::alloc::Allocator) trait.
 
 #![no_std]
+#![cfg_attr(feature = "nightly", feature(allocator_api))]
 
-#[cfg(feature = "std")]
-extern crate core;
+// Tests always get std, whatever the features say.
+#[cfg(any(feature = "std", test))]
+#[macro_use]
+extern crate std;
 
 #[cfg(feature = "alloc")]
 extern crate alloc;
 
-#[macro_use]
-extern crate std;
+// So that `#[trolloc::test]` works in our own tests too.
+#[cfg(test)]
+extern crate self as trolloc;
 
+#[allow(deprecated)]
 pub mod allocator;
 pub mod gjallocator;
+pub mod troll;
+pub mod policy;
+#[cfg(feature = "alloc")]
+pub mod snapshot;
+#[cfg(feature = "alloc")]
+pub mod leaks;
+#[cfg(feature = "std")]
+pub mod harness;
+mod free_list;
 #[cfg(test)]
 mod tests;
 
 use core::alloc::Layout;
+#[allow(deprecated)]
 use allocator::*;
 
+#[cfg(feature = "std")]
+pub use trolloc_macros::test;
+
 /// Generates a random number using xorshift
 /// 
 /// Credit: Marsaglia, "Xorshift RNGs", https://www.jstatsoft.org/article/view/v008i14
@@ -49,34 +92,59 @@ pub fn xorshift(state: usize) -> usize {
     x
 }
 
+/// Amount [`wyrand`] adds to its state, which is what to step the state by to get the next number.
+pub(crate) const WYRAND_INCREMENT: u64 = 0xa0761d6478bd642f;
+
 /// Generates a random 64-bit number using wyrand.
 ///
 /// Credit: Branden Brown (https://github.com/zephyrtronium)
 ///         Wang Yi (https://github.com/wangyi-fudan/wyhash)
 pub fn wyrand(x: u64) -> u64 {
-    let x = x + 0xa0761d6478bd642f;
+    let x = x.wrapping_add(WYRAND_INCREMENT);
     let v = (x as u128) * (x as u128 ^ 0xe7037ed1a0b428db);
     (v ^ v >> 64) as u64
 }
 
+/// Decide which of `live` allocations to troll, if any, from a random number.
+/// 
+/// This is the trolling algorithm shared by [`gjallocator`](crate::gjallocator) and
+/// [`troll`](crate::troll): pick a random allocation index, then only go through with it if a
+/// random bit of that index happens to be set.
+pub(crate) fn troll_victim(rand_result: usize, live: usize) -> Option<usize> {
+    if live == 0 {
+        return None;
+    }
+
+    let randex: usize = rand_result % live;
+    let rand_bit: usize = (rand_result % (core::mem::size_of::<usize>() * 8)).saturating_sub(1);
+    if ((randex & (1 << rand_bit)) >> rand_bit) == 1 {
+        Some(randex)
+    } else {
+        None
+    }
+}
+
 #[deprecated]
+#[allow(deprecated)]
 /// Mallocs a block of the specified size using the given allocator.
-pub fn malloc(allocer: &mut Trollocator, size: usize) -> *mut u8 {
+pub fn malloc<const N: usize>(allocer: &mut Trollocator<N>, size: usize) -> *mut u8 {
     unsafe { allocer.malloc(Layout::from_size_align_unchecked(size, allocator::ALIGNMENT)) }
 }
 
 #[deprecated]
+#[allow(deprecated, clippy::not_unsafe_ptr_arg_deref)]
 /// Reallocates a block of memory to be the specified size.
 /// 
 /// The pointer argument must be the **exact** same pointer returned by `malloc`.
-pub fn realloc(allocer: &mut Trollocator, ptr: *mut u8, size: usize) -> *mut u8 {
+pub fn realloc<const N: usize>(allocer: &mut Trollocator<N>, ptr: *mut u8, size: usize) -> *mut u8 {
     unsafe { allocer.realloc(ptr, Layout::from_size_align_unchecked(size, allocator::ALIGNMENT))} 
 }
 
 #[deprecated]
+#[allow(deprecated, clippy::not_unsafe_ptr_arg_deref)]
 /// Frees a block of memory using the given allocator. 
 /// 
 /// The pointer argument must be the **exact** same pointer returned by `malloc`.
-pub fn free(allocer: &mut Trollocator, ptr: *mut u8) {
+pub fn free<const N: usize>(allocer: &mut Trollocator<N>, ptr: *mut u8) {
     unsafe { allocer.free(ptr); }
 }
\ No newline at end of file
@@ -1,24 +1,361 @@
-use trolloc::gjallocator::Trollocator;
+//! # trolloc
+//!
+//! Run a program with `trolloc-preload` standing in for its `malloc`, and tell us how it went:
+//!
+//! ```sh
+//! cargo build --release --workspace --features trolloc-preload/trolling
+//! target/release/trolloc --seed 1 --probability 0.01 -- ./some-program --some-flag
+//! ```
+//!
+//! When the program is done, a summary of what got trolled and how the program ended is printed
+//! to stderr, and `trolloc` exits the way the program did, so it can be dropped into CI scripts
+//! as is. Programs killed by a signal exit with 128 plus the signal number, like in a shell.
 
-#[global_allocator]
-static mut ALLOCATOR: Trollocator = Trollocator::new();
+use std::{
+    collections::hash_map::RandomState,
+    env,
+    ffi::OsString,
+    fs::{self, OpenOptions},
+    hash::{BuildHasher, Hasher},
+    io::ErrorKind,
+    os::unix::fs::OpenOptionsExt,
+    path::PathBuf,
+    process::{self, Command, ExitStatus},
+};
+
+use trolloc::policy::{env as vars, TrollAction, TrollPolicy};
+
+const USAGE: &str = "\
+Usage: trolloc [OPTIONS] [--] <PROGRAM> [ARGS...]
+
+Run PROGRAM with the trolling allocator preloaded.
+
+Options:
+  -s, --seed <SEED>                Seed the random number generator, to replay a run
+  -p, --probability <CHANCE>       Chance that an allocation trolls something, from 0 to 1
+  -H, --heap-size <SIZE>           Heap size in bytes, with an optional K, M or G suffix [default: 64M]
+  -a, --actions <ACTIONS>          Comma separated troll actions, or `all`, or `none` [default: free]
+      --undersize <BYTES>          How many bytes short undersized allocations come up [default: 8]
+      --stall <MICROS>             Longest stall, in microseconds [default: 1000]
+  -l, --log <PATH>                 Keep the troll log at PATH, one line per troll, replacing
+                                   whatever was there
+      --preload <PATH>             Path to libtrolloc_preload.so [default: next to trolloc]
+  -h, --help                       Print this and exit
+";
+
+/// Name of the preload library, as cargo builds it.
+const PRELOAD_LIBRARY: &str = "libtrolloc_preload.so";
+
+/// Exit code for when the program never ran, because we got our own arguments wrong.
+const USAGE_ERROR: i32 = 2;
+
+/// Everything from the command line.
+#[derive(Default)]
+struct Options {
+    seed: Option<String>,
+    probability: Option<String>,
+    heap_size: Option<usize>,
+    actions: Option<String>,
+    undersize: Option<String>,
+    stall: Option<String>,
+    log: Option<PathBuf>,
+    preload: Option<PathBuf>,
+    program: Vec<OsString>,
+}
+
+impl Options {
+    /// Parse the command line, minus the name of the binary.
+    fn parse(mut args: impl Iterator<Item = OsString>) -> Result<Self, String> {
+        let mut options = Self::default();
+
+        while let Some(arg) = args.next() {
+            let flag = match arg.to_str() {
+                Some("--") => {
+                    options.program.extend(args);
+                    break;
+                }
+                Some(flag) if flag.starts_with('-') && flag.len() > 1 => flag.to_string(),
+                // The program, and everything after it is its business
+                _ => {
+                    options.program.push(arg);
+                    options.program.extend(args);
+                    break;
+                }
+            };
+
+            if flag == "-h" || flag == "--help" {
+                print!("{USAGE}");
+                process::exit(0);
+            }
+
+            let value = args.next().ok_or_else(|| format!("{flag} needs a value"))?;
+            let text = || value.to_str().map(str::to_string).ok_or_else(|| format!("{flag} is not valid UTF-8"));
+            match flag.as_str() {
+                "-s" | "--seed" => options.seed = Some(text()?),
+                "-p" | "--probability" => options.probability = Some(text()?),
+                "-H" | "--heap-size" => options.heap_size = Some(parse_size(&text()?)?),
+                "-a" | "--actions" => options.actions = Some(text()?),
+                "--undersize" => options.undersize = Some(text()?),
+                "--stall" => options.stall = Some(text()?),
+                "-l" | "--log" => options.log = Some(value.into()),
+                "--preload" => options.preload = Some(value.into()),
+                _ => return Err(format!("unknown option {flag}")),
+            }
+        }
+
+        if options.program.is_empty() {
+            return Err("no program to run".to_string());
+        }
+
+        // The preload cannot tell an empty list from none at all, and would troll by default
+        if options.actions.as_deref().is_some_and(|actions| actions.trim().is_empty()) {
+            return Err("--actions needs at least one action, or `none`".to_string());
+        }
+
+        // Same parser the preload uses, so that mistakes show up here and not in the child
+        TrollPolicy::from_env(|name| match name {
+            vars::SEED => options.seed.as_deref(),
+            vars::PROBABILITY => options.probability.as_deref(),
+            vars::ACTIONS => options.actions.as_deref(),
+            vars::UNDERSIZE => options.undersize.as_deref(),
+            vars::STALL => options.stall.as_deref(),
+            _ => None,
+        })?;
+
+        Ok(options)
+    }
+}
+
+/// Parse a size in bytes, with an optional K, M or G suffix.
+fn parse_size(size: &str) -> Result<usize, String> {
+    let (digits, shift) = match size.trim().to_ascii_uppercase() {
+        size if size.ends_with('K') => (size[..size.len() - 1].to_string(), 10),
+        size if size.ends_with('M') => (size[..size.len() - 1].to_string(), 20),
+        size if size.ends_with('G') => (size[..size.len() - 1].to_string(), 30),
+        size => (size, 0),
+    };
+
+    digits
+        .parse::<usize>()
+        .ok()
+        .and_then(|size| size.checked_mul(1 << shift))
+        .ok_or_else(|| format!("{size} is not a heap size"))
+}
+
+/// Count the entries in a troll log, by action. Some trolls take more than one, like time bombs
+/// going off after being planted.
+fn summarize(log: &str) -> Vec<(&'static str, usize)> {
+    TrollAction::ALL
+        .iter()
+        .map(|action| {
+            let count = log.lines().filter(|line| line.split(' ').next() == Some(action.name())).count();
+            (action.name(), count)
+        })
+        .collect()
+}
+
+/// Describe how the program ended, and the code to exit with to match.
+fn describe(status: ExitStatus) -> (String, i32) {
+    use std::os::unix::process::ExitStatusExt;
+
+    if let Some(code) = status.code() {
+        return (format!("exited with status {code}"), code);
+    }
+
+    let signal = status.signal().unwrap_or(0);
+    let name = match signal {
+        4 => "SIGILL",
+        6 => "SIGABRT",
+        7 => "SIGBUS",
+        8 => "SIGFPE",
+        9 => "SIGKILL",
+        11 => "SIGSEGV",
+        15 => "SIGTERM",
+        _ => "a signal",
+    };
+    let core = if status.core_dumped() { ", core dumped" } else { "" };
+    (format!("killed by {name} ({signal}){core}"), 128 + signal)
+}
+
+/// Make a fresh, empty log in the temp directory that nobody else can read or swap out from
+/// under us: the name is random, and it has to not exist yet.
+fn temp_log() -> Result<PathBuf, String> {
+    for _ in 0..16 {
+        // Hashers are randomly keyed, which is all the randomness std hands out
+        let name = format!("trolloc-{}-{:016x}.log", process::id(), RandomState::new().build_hasher().finish());
+        let path = env::temp_dir().join(name);
+
+        match OpenOptions::new().write(true).create_new(true).mode(0o600).open(&path) {
+            Ok(_) => return Ok(path),
+            Err(err) if err.kind() == ErrorKind::AlreadyExists => continue,
+            Err(err) => return Err(format!("could not make a troll log: {err}")),
+        }
+    }
+
+    Err("could not make a troll log, the temp directory is full of them".to_string())
+}
+
+/// Run the program and report back. Returns the code to exit with.
+fn run(options: Options) -> Result<i32, String> {
+    let preload = match options.preload {
+        Some(preload) => preload,
+        None => env::current_exe().map_err(|err| err.to_string())?.with_file_name(PRELOAD_LIBRARY),
+    };
+    if !preload.is_file() {
+        return Err(format!("{} not found, build it with `cargo build -p trolloc-preload --features trolling`", preload.display()));
+    }
+
+    // Without --log, the log is only kept around long enough to summarize it
+    let (log, keep_log) = match options.log {
+        Some(log) => {
+            // The preload appends, so start from nothing, like --help says
+            let _ = fs::remove_file(&log);
+            (log, true)
+        }
+        None => (temp_log()?, false),
+    };
+
+    // Keep whatever else was being preloaded, after us so that our malloc wins
+    let mut ld_preload = OsString::from(preload.as_os_str());
+    if let Some(existing) = env::var_os("LD_PRELOAD").filter(|existing| !existing.is_empty()) {
+        ld_preload.push(":");
+        ld_preload.push(existing);
+    }
+
+    let mut command = Command::new(&options.program[0]);
+    command.args(&options.program[1..]).env("LD_PRELOAD", ld_preload).env(vars::LOG, &log);
+    for (name, value) in [
+        (vars::SEED, options.seed),
+        (vars::PROBABILITY, options.probability),
+        (vars::ACTIONS, options.actions),
+        (vars::UNDERSIZE, options.undersize),
+        (vars::STALL, options.stall),
+        (vars::HEAP_SIZE, options.heap_size.map(|size| size.to_string())),
+    ] {
+        match value {
+            Some(value) => command.env(name, value),
+            // Do not let our own environment leak into the policy
+            None => command.env_remove(name),
+        };
+    }
+
+    let status = command
+        .status()
+        .map_err(|err| format!("could not run {}: {err}", options.program[0].to_string_lossy()))?;
+
+    let trolls = fs::read_to_string(&log).unwrap_or_default();
+    if !keep_log {
+        let _ = fs::remove_file(&log);
+    }
+
+    let counts = summarize(&trolls);
+    let total: usize = counts.iter().map(|(_, count)| count).sum();
+    let (ending, code) = describe(status);
+
+    eprintln!("trolloc: {} {ending}", options.program[0].to_string_lossy());
+    eprintln!("trolloc: {total} troll log entries");
+    for (name, count) in counts.into_iter().filter(|&(_, count)| count > 0) {
+        eprintln!("trolloc:   {name}: {count}");
+    }
+    if keep_log {
+        eprintln!("trolloc: troll log at {}", log.display());
+    }
+
+    Ok(code)
+}
 
 fn main() {
-    let _s = format!("hello world");
-    println!("{}", _s);
+    let options = match Options::parse(env::args_os().skip(1)) {
+        Ok(options) => options,
+        Err(message) => {
+            eprintln!("trolloc: {message}\n\n{USAGE}");
+            process::exit(USAGE_ERROR);
+        }
+    };
+
+    match run(options) {
+        Ok(code) => process::exit(code),
+        Err(message) => {
+            eprintln!("trolloc: {message}");
+            process::exit(USAGE_ERROR);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::process::ExitStatusExt;
+
+    fn parse(args: &[&str]) -> Result<Options, String> {
+        Options::parse(args.iter().map(OsString::from))
+    }
+
+    #[test]
+    fn sizes_parse() {
+        assert_eq!(Ok(4096), parse_size("4096"));
+        assert_eq!(Ok(64 << 10), parse_size("64k"));
+        assert_eq!(Ok(64 << 20), parse_size(" 64M "));
+        assert_eq!(Ok(2 << 30), parse_size("2G"));
+
+        // Too big to be a size at all
+        assert!(parse_size("99999999999999999999").is_err());
+        assert!(parse_size(&format!("{}G", usize::MAX >> 20)).is_err());
+
+        assert!(parse_size("bingus").is_err());
+        assert!(parse_size("").is_err());
+        assert!(parse_size("-1K").is_err());
+    }
+
+    #[test]
+    fn logs_summarize() {
+        let log = "free 0x1000 64\nbitflip 0x2000 32 offset=3 bit=1\nfree 0x3000 16\nfreedom 0x4000 8\n";
+        let counts = summarize(log);
 
-    let mut vec = vec![0u8];
+        assert_eq!(TrollAction::ALL.len(), counts.len());
+        assert!(counts.contains(&("free", 2)));
+        assert!(counts.contains(&("bitflip", 1)));
+        assert_eq!(3, counts.iter().map(|(_, count)| count).sum::<usize>());
 
-    for i in 0..=128u8 {
-        vec.push(i as u8);
+        assert!(summarize("").iter().all(|&(_, count)| count == 0));
     }
 
-    for i in 0..=127u8 {
-        vec.push(i as u8);
+    #[test]
+    fn endings_describe() {
+        assert_eq!(("exited with status 3".to_string(), 3), describe(ExitStatus::from_raw(3 << 8)));
+        assert_eq!(("killed by SIGSEGV (11)".to_string(), 139), describe(ExitStatus::from_raw(11)));
+        assert_eq!(("killed by SIGABRT (6), core dumped".to_string(), 134), describe(ExitStatus::from_raw(6 | 0x80)));
+        assert_eq!(("killed by a signal (10)".to_string(), 138), describe(ExitStatus::from_raw(10)));
     }
 
-    let _s2 = format!("hello world 2");
-    println!("{}", _s2);
+    #[test]
+    fn options_parse() {
+        let options = parse(&["-s", "42", "--heap-size", "1M", "-l", "trolls.log", "./bingus", "-s", "--bongus"]).unwrap();
+        assert_eq!(Some("42"), options.seed.as_deref());
+        assert_eq!(Some(1 << 20), options.heap_size);
+        assert_eq!(Some(PathBuf::from("trolls.log")), options.log);
+        // Flags after the program are its own
+        assert_eq!(vec!["./bingus", "-s", "--bongus"], options.program);
 
-    core::mem::drop(vec);
-}
\ No newline at end of file
+        let options = parse(&["-p", "0.5", "--", "-bingus"]).unwrap();
+        assert_eq!(vec!["-bingus"], options.program);
+
+        assert_eq!(Some("unknown option --bingus".to_string()), parse(&["--bingus", "1", "./bingus"]).err());
+        assert_eq!(Some("-s needs a value".to_string()), parse(&["-s"]).err());
+        assert_eq!(Some("no program to run".to_string()), parse(&["-s", "42"]).err());
+        assert!(parse(&["-p", "1.5", "./bingus"]).is_err());
+        assert!(parse(&["-a", "free,bingus", "./bingus"]).is_err());
+        assert!(parse(&["-a", "", "./bingus"]).is_err());
+        assert_eq!(Some("none"), parse(&["-a", "none", "./bingus"]).unwrap().actions.as_deref());
+    }
+
+    #[test]
+    fn temp_logs_are_fresh() {
+        let (first, second) = (temp_log().unwrap(), temp_log().unwrap());
+        assert_ne!(first, second);
+        assert_eq!(Some(0), fs::metadata(&first).ok().map(|metadata| metadata.len()));
+
+        let _ = fs::remove_file(first);
+        let _ = fs::remove_file(second);
+    }
+}
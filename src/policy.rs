@@ -0,0 +1,459 @@
+//! # Policy
+//!
+//! How, and how often, to troll.
+//!
+//! By default, trolling is seeded by ASLR, so that no two runs troll alike, and whether an
+//! allocation trolls anything is a coin flip on a random bit of a random index. A
+//! [`TrollPolicy`] can instead fix the seed, to replay a run that fell over, set the probability
+//! of trolling outright, pick which [`TrollAction`]s are allowed, and hook every troll, e.g. to
+//! log it.
+//!
+//! Policies are given to the [`gjallocator`](crate::gjallocator) with
+//! [`Trollocator::with_policy`](crate::gjallocator::Trollocator::with_policy). The
+//! `trolloc-preload` library reads one from the [`env`] variables, which the `trolloc` command
+//! line tool sets from its flags.
+
+use core::fmt;
+
+use crate::{troll_victim, wyrand, WYRAND_INCREMENT};
+
+/// Names of the environment variables `trolloc-preload` reads its configuration from.
+pub mod env {
+    /// Seed for the random number generator, a 64-bit integer.
+    pub const SEED: &str = "TROLLOC_SEED";
+    /// Chance that an allocation trolls something, from 0 to 1.
+    pub const PROBABILITY: &str = "TROLLOC_PROBABILITY";
+    /// Comma separated [`TrollAction`](super::TrollAction) names, or `all`, or `none`.
+    pub const ACTIONS: &str = "TROLLOC_ACTIONS";
+    /// How many bytes short undersized allocations come up.
+    pub const UNDERSIZE: &str = "TROLLOC_UNDERSIZE";
+    /// Longest stall, in microseconds.
+    pub const STALL: &str = "TROLLOC_STALL";
+    /// Size of the heap in bytes.
+    pub const HEAP_SIZE: &str = "TROLLOC_HEAP_SIZE";
+    /// Path of the file to log trolls to, one per line.
+    pub const LOG: &str = "TROLLOC_LOG";
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Something trolling can do to a program. Only [`Free`](TrollAction::Free) happens unless
+/// others are asked for.
+pub enum TrollAction {
+    /// Free a live allocation out from under its owner. The original troll.
+    Free,
+    /// Flip a few random bits in a live allocation, like a cosmic ray would. Only ever in the
+    /// payload, the allocator's own bookkeeping is left alone.
+    BitFlip,
+    /// Free a live allocation out from under its owner, but not yet: some number of allocations
+    /// or frees later, long after anyone would think to look here.
+    TimeBomb,
+    /// Hand out an allocation a few bytes shorter than asked for, right up against the next
+    /// block, so that writing to the end of it scribbles over the neighbour. Never a zeroed one,
+    /// which the allocator would have to scribble over itself.
+    Undersize,
+    /// Hand out an allocation that is not aligned like it was asked to be, off by a byte or by
+    /// half the alignment. Next to nothing survives it.
+    Misalign,
+    /// Do a `realloc` badly: copy only some of the old contents over, claim to have grown the
+    /// allocation without growing it, or move it and forget to free the old one.
+    LazyRealloc,
+    /// Have `alloc_zeroed` miss a spot, leaving whatever the last owner of the memory put there.
+    Unzeroed,
+    /// Hand out somewhere in the middle of a live allocation instead of a block of its own, so
+    /// that two owners scribble over each other.
+    Alias,
+    /// Swap the contents of a live allocation with another one of the same size. The heap is
+    /// none the wiser, but whatever was pointing at either of them now sees the other's data.
+    Swap,
+    /// Have `dealloc` do nothing at all, leaving the block allocated forever, so that memory use
+    /// only ever goes up.
+    Leak,
+    /// Make an allocation or free take its time, by spinning, or with `std`, by sleeping. Only
+    /// slow, not wrong.
+    Stall,
+}
+
+impl TrollAction {
+    /// Every action there is.
+    pub const ALL: &'static [TrollAction] = &[TrollAction::Free, TrollAction::BitFlip, TrollAction::TimeBomb, TrollAction::Undersize, TrollAction::Misalign, TrollAction::LazyRealloc, TrollAction::Unzeroed, TrollAction::Alias, TrollAction::Swap, TrollAction::Leak, TrollAction::Stall];
+
+    /// Get the name of the action, as used in [`env::ACTIONS`] and troll logs.
+    pub fn name(self) -> &'static str {
+        match self {
+            TrollAction::Free => "free",
+            TrollAction::BitFlip => "bitflip",
+            TrollAction::TimeBomb => "timebomb",
+            TrollAction::Undersize => "undersize",
+            TrollAction::Misalign => "misalign",
+            TrollAction::LazyRealloc => "lazyrealloc",
+            TrollAction::Unzeroed => "unzeroed",
+            TrollAction::Alias => "alias",
+            TrollAction::Swap => "swap",
+            TrollAction::Leak => "leak",
+            TrollAction::Stall => "stall",
+        }
+    }
+
+    /// Check whether the action is done to a random live allocation, the victim. The others are
+    /// done to whatever the allocator is in the middle of, like the allocation being made.
+    pub fn picks_victim(self) -> bool {
+        matches!(self, TrollAction::Free | TrollAction::BitFlip | TrollAction::TimeBomb | TrollAction::Swap)
+    }
+
+    /// Check whether the action has to be asked for by name, or with `all`, instead of being on
+    /// by default. Which is every action but [`Free`](TrollAction::Free), so that classic
+    /// trolling stays classic.
+    pub const fn opt_in(self) -> bool {
+        !matches!(self, TrollAction::Free)
+    }
+
+    /// Look up an action by [`name`](TrollAction::name).
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.iter().copied().find(|action| action.name() == name)
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// A set of [`TrollAction`]s.
+pub struct TrollActions(u32);
+
+impl TrollActions {
+    /// No trolling at all.
+    pub const NONE: Self = Self(0);
+    /// Every action there is.
+    pub const ALL: Self = Self::every(true);
+    /// Every action that is not [opt in](TrollAction::opt_in), which is only
+    /// [`Free`](TrollAction::Free).
+    pub const DEFAULT: Self = Self::every(false);
+
+    /// Get every action, opt in ones too if `opt_in`.
+    const fn every(opt_in: bool) -> Self {
+        let mut bits = 0;
+        let mut index = 0;
+        while index < TrollAction::ALL.len() {
+            let action = TrollAction::ALL[index];
+            if opt_in || !action.opt_in() {
+                bits |= 1 << action as u32;
+            }
+            index += 1;
+        }
+        Self(bits)
+    }
+
+    /// Get the set with `action` added to it.
+    pub const fn with(self, action: TrollAction) -> Self {
+        Self(self.0 | 1 << action as u32)
+    }
+
+    /// Check whether `action` is in the set.
+    pub const fn contains(self, action: TrollAction) -> bool {
+        self.0 & 1 << action as u32 != 0
+    }
+
+    /// Get the number of actions in the set.
+    pub const fn len(self) -> usize {
+        self.0.count_ones() as usize
+    }
+
+    /// Check whether the set is empty.
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Iterate over the actions in the set.
+    pub fn iter(self) -> impl Iterator<Item = TrollAction> {
+        TrollAction::ALL.iter().copied().filter(move |&action| self.contains(action))
+    }
+
+    /// Parse comma separated action names, or `all`, which includes the opt in ones, or `none`.
+    pub fn from_names(names: &str) -> Result<Self, &'static str> {
+        match names.trim() {
+            "all" => return Ok(Self::ALL),
+            "none" => return Ok(Self::NONE),
+            _ => {}
+        }
+
+        names
+            .split(',')
+            .map(str::trim)
+            .filter(|name| !name.is_empty())
+            .try_fold(Self::NONE, |actions, name| {
+                TrollAction::from_name(name).map(|action| actions.with(action)).ok_or("unknown troll action")
+            })
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// What a [`TrollAction::TimeBomb`] counts down.
+pub enum Fuse {
+    /// Allocations, including the ones `realloc` makes.
+    Allocs,
+    /// Frees by their owners, including the ones `realloc` makes.
+    Deallocs,
+}
+
+impl Fuse {
+    /// Get the name of the fuse, as used in troll logs.
+    pub fn name(self) -> &'static str {
+        match self {
+            Fuse::Allocs => "allocs",
+            Fuse::Deallocs => "deallocs",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// Whatever else there is to know about a troll, depending on the action.
+pub enum TrollDetail {
+    /// Nothing else.
+    None,
+    /// A bit got flipped.
+    BitFlip {
+        /// Byte of the payload it is in.
+        offset: usize,
+        /// Which bit of that byte, 0 being the lowest.
+        bit: u8,
+    },
+    /// A time bomb got planted.
+    Armed {
+        /// How many to count down before it goes off.
+        after: usize,
+        /// What to count.
+        fuse: Fuse,
+    },
+    /// A time bomb went off, and the allocation is free now.
+    Detonated,
+    /// The allocation came up short.
+    Undersized {
+        /// By how many bytes.
+        by: usize,
+    },
+    /// The allocation is off from where it should be.
+    Misaligned {
+        /// By how many bytes.
+        offset: usize,
+    },
+    /// The allocation moved, but only the start of it came along, the rest is whatever was there.
+    PartialCopy {
+        /// How many bytes did.
+        copied: usize,
+    },
+    /// The allocation was supposed to grow, and did not.
+    NotGrown {
+        /// How big it was supposed to get.
+        wanted: usize,
+    },
+    /// The allocation moved, and the old one is still allocated, with nobody left to free it.
+    OldKept {
+        /// Payload of the old one.
+        old: usize,
+    },
+    /// Some of an allocation that was supposed to be zeroed is not.
+    Stale {
+        /// Byte of the payload the bytes that are not start at.
+        offset: usize,
+        /// How many of them there are.
+        len: usize,
+    },
+    /// The allocation is inside another live one.
+    Aliased {
+        /// Payload of the other one.
+        owner: usize,
+    },
+    /// The allocation traded contents with another live one.
+    Swapped {
+        /// Payload of the other one.
+        with: usize,
+    },
+    /// The call took its time.
+    Stalled {
+        /// How long, in microseconds.
+        micros: u64,
+    },
+}
+
+#[derive(Clone, Copy, Debug)]
+/// A troll, as passed to [`TrollPolicy::log`].
+/// 
+/// Displays as a line of the troll log: the action name, the address and the size, then the
+/// details, e.g. `bitflip 0x7f4369000100 64 offset=12 bit=3`.
+pub struct TrollEvent {
+    /// What was done.
+    pub action: TrollAction,
+    /// Payload of the allocation it was done to.
+    pub ptr: *mut u8,
+    /// Leasable size of that allocation.
+    pub size: usize,
+    /// The rest.
+    pub detail: TrollDetail,
+}
+
+impl fmt::Display for TrollEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {:#x} {}", self.action.name(), self.ptr as usize, self.size)?;
+
+        match self.detail {
+            TrollDetail::None => Ok(()),
+            TrollDetail::BitFlip { offset, bit } => write!(f, " offset={offset} bit={bit}"),
+            TrollDetail::Armed { after, fuse } => write!(f, " after={after} {}", fuse.name()),
+            TrollDetail::Detonated => write!(f, " detonated"),
+            TrollDetail::Undersized { by } => write!(f, " by={by}"),
+            TrollDetail::Misaligned { offset } => write!(f, " offset={offset}"),
+            TrollDetail::PartialCopy { copied } => write!(f, " copied={copied}"),
+            TrollDetail::NotGrown { wanted } => write!(f, " wanted={wanted}"),
+            TrollDetail::OldKept { old } => write!(f, " old={old:#x}"),
+            TrollDetail::Stale { offset, len } => write!(f, " offset={offset} len={len}"),
+            TrollDetail::Aliased { owner } => write!(f, " owner={owner:#x}"),
+            TrollDetail::Swapped { with } => write!(f, " with={with:#x}"),
+            TrollDetail::Stalled { micros } => write!(f, " micros={micros}"),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+/// How, and how often, to troll.
+pub struct TrollPolicy {
+    /// Seed for the random number generator. Without one, ASLR is the seed, and no two runs
+    /// troll alike.
+    pub seed: Option<u64>,
+    /// Chance that an allocation trolls something, from 0 to 1, for each kind of troll. Without
+    /// one, trolling a victim is a coin flip on a random bit of a random index, which comes up
+    /// heads a bit less than half the time, and the other trolls happen one in
+    /// [`DEFAULT_ODDS`](TrollPolicy::DEFAULT_ODDS) times.
+    pub probability: Option<f64>,
+    /// What trolling is allowed to do.
+    pub actions: TrollActions,
+    /// How many bytes short [`TrollAction::Undersize`] allocations come up. Blocks come in whole
+    /// words, so this is rounded up to one.
+    pub undersize: usize,
+    /// Longest [`TrollAction::Stall`], in microseconds. Each one is anywhere from a microsecond
+    /// up to this.
+    pub stall: u64,
+    /// Called after every troll. This runs inside the allocator, so it must not allocate.
+    pub log: Option<fn(&TrollEvent)>,
+}
+
+impl TrollPolicy {
+    /// Classic trolling: seeded by ASLR, the coin flip, freeing victims and nothing else.
+    pub const DEFAULT: Self = Self { seed: None, probability: None, actions: TrollActions::DEFAULT, undersize: 8, stall: 1000, log: None };
+
+    /// Odds of the trolls that do not pick a victim, when there is no probability.
+    pub const DEFAULT_ODDS: u64 = 16;
+
+    /// Read a policy from the [`env`] variables, looked up with `var`. Missing variables keep
+    /// their defaults.
+    pub fn from_env<'a>(var: impl Fn(&str) -> Option<&'a str>) -> Result<Self, &'static str> {
+        let mut policy = Self::DEFAULT;
+
+        if let Some(seed) = var(env::SEED) {
+            policy.seed = Some(seed.trim().parse().map_err(|_| "troll seed is not a 64-bit integer")?);
+        }
+
+        if let Some(probability) = var(env::PROBABILITY) {
+            match probability.trim().parse::<f64>() {
+                Ok(probability) if (0.0..=1.0).contains(&probability) => policy.probability = Some(probability),
+                _ => return Err("troll probability is not a number from 0 to 1"),
+            }
+        }
+
+        if let Some(actions) = var(env::ACTIONS) {
+            policy.actions = TrollActions::from_names(actions)?;
+        }
+
+        if let Some(undersize) = var(env::UNDERSIZE) {
+            policy.undersize = undersize.trim().parse().map_err(|_| "undersize is not a number of bytes")?;
+        }
+
+        if let Some(stall) = var(env::STALL) {
+            policy.stall = stall.trim().parse().map_err(|_| "stall is not a number of microseconds")?;
+        }
+
+        Ok(policy)
+    }
+}
+
+impl Default for TrollPolicy {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// A [`TrollPolicy`] and the random number generator state it needs.
+pub(crate) struct Troller {
+    pub(crate) policy: TrollPolicy,
+    /// State of the random number generator.
+    rng: u64,
+}
+
+impl Troller {
+    /// Start trolling by `policy`.
+    pub(crate) const fn new(policy: TrollPolicy) -> Self {
+        let rng = match policy.seed {
+            Some(seed) => seed,
+            None => 0,
+        };
+
+        Self { policy, rng }
+    }
+
+    /// Generate a random number. Unless the policy is seeded, `entropy` is mixed in first.
+    pub(crate) fn next(&mut self, entropy: u64) -> u64 {
+        if self.policy.seed.is_none() {
+            self.rng ^= entropy;
+        }
+
+        let rand_result = wyrand(self.rng);
+        self.rng = self.rng.wrapping_add(WYRAND_INCREMENT);
+        rand_result
+    }
+
+    /// Get the allowed actions that pick a victim.
+    fn victim_actions(&self) -> impl Iterator<Item = TrollAction> {
+        self.policy.actions.iter().filter(|action| action.picks_victim())
+    }
+
+    /// Decide which of `live` allocations to troll, if any.
+    pub(crate) fn victim(&mut self, entropy: u64, live: usize) -> Option<usize> {
+        // Nothing to do to a victim anyway
+        self.victim_actions().next()?;
+
+        let rand_result = self.next(entropy) as usize;
+        match self.policy.probability {
+            None => troll_victim(rand_result, live),
+            Some(_) if live == 0 => None,
+            Some(probability) => self.chance(entropy, probability).then_some(rand_result % live),
+        }
+    }
+
+    /// Pick one of the allowed actions that pick a victim. There must be at least one.
+    pub(crate) fn action(&mut self, entropy: u64) -> TrollAction {
+        let index = self.next(entropy) as usize % self.victim_actions().count();
+        self.victim_actions().nth(index).unwrap_or(TrollAction::Free)
+    }
+
+    /// Decide whether to do `action`, one that does not pick a victim, this time around.
+    pub(crate) fn roll(&mut self, entropy: u64, action: TrollAction) -> bool {
+        if !self.policy.actions.contains(action) {
+            return false;
+        }
+
+        match self.policy.probability {
+            None => self.next(entropy).is_multiple_of(TrollPolicy::DEFAULT_ODDS),
+            Some(probability) => self.chance(entropy, probability),
+        }
+    }
+
+    /// Come up true with `probability`.
+    fn chance(&mut self, entropy: u64, probability: f64) -> bool {
+        // Top 53 bits make a uniformly distributed f64 from 0 to 1
+        let roll = (self.next(entropy) >> 11) as f64 / (1u64 << 53) as f64;
+        roll < probability
+    }
+
+    /// Tell the policy's log about a troll.
+    pub(crate) fn log(&self, action: TrollAction, ptr: *mut u8, size: usize, detail: TrollDetail) {
+        if let Some(log) = self.policy.log {
+            log(&TrollEvent { action, ptr, size, detail });
+        }
+    }
+}
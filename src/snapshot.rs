@@ -0,0 +1,139 @@
+//! # Snapshot
+//!
+//! Pictures of a [`gjallocator`](crate::gjallocator) heap, for attaching to bug reports when a
+//! trolled program falls over.
+//!
+//! [`Trollocator::snapshot`](crate::gjallocator::Trollocator::snapshot) copies the physical block
+//! map out of the heap, so that it can be looked at after the heap has moved on.
+//! [`HeapSnapshot::to_json`] is for tools. [`HeapSnapshot::to_svg`] is for humans: a strip with
+//! one box per block, where a healthy heap is a few big boxes and a fragmented one looks like a
+//! barcode.
+//!
+//! Snapshots need somewhere to live, so this needs the `alloc` feature.
+
+use alloc::{string::String, vec::Vec};
+use core::fmt::Write;
+
+/// Width of the block strip in SVG pictures.
+const SVG_WIDTH: usize = 1024;
+/// Height of the block strip in SVG pictures, there is a caption below it.
+const SVG_STRIP_HEIGHT: usize = 32;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// What a block was up to when the snapshot was taken.
+pub enum BlockState {
+    /// Handed out, and as far as we know still in use.
+    Allocated,
+    /// Free.
+    Free,
+    /// Free, because trolling freed (some of) it. Its owner might not be done with it.
+    Trolled,
+}
+
+impl BlockState {
+    /// Get the name of the state, as it appears in JSON and SVG.
+    pub fn name(self) -> &'static str {
+        match self {
+            BlockState::Allocated => "allocated",
+            BlockState::Free => "free",
+            BlockState::Trolled => "trolled",
+        }
+    }
+
+    /// Get the fill colour of the state in SVG.
+    fn colour(self) -> &'static str {
+        match self {
+            BlockState::Allocated => "#4e79a7",
+            BlockState::Free => "#e8e8e8",
+            BlockState::Trolled => "#e15759",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+/// A single physical block.
+pub struct BlockSnapshot {
+    /// Distance from the start of the heap to the block, in bytes.
+    pub offset: usize,
+    /// Leasable bytes, not counting the header and footer.
+    pub size: usize,
+    /// What the block was up to.
+    pub state: BlockState,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+/// The physical block map of a heap at some point in time.
+pub struct HeapSnapshot {
+    /// Size of the heap in bytes, not counting the metadata at its head.
+    pub heap_size: usize,
+    /// Every block in the heap, in address order.
+    pub blocks: Vec<BlockSnapshot>,
+}
+
+impl HeapSnapshot {
+    /// Serialize to JSON, e.g. `{"heap_size":4048,"blocks":[{"offset":0,"size":16,"state":"allocated"}]}`.
+    pub fn to_json(&self) -> String {
+        let mut json = String::new();
+
+        // Writing to a String cannot fail, so the results are ignored all over the place
+        let _ = write!(json, "{{\"heap_size\":{},\"blocks\":[", self.heap_size);
+        for (index, block) in self.blocks.iter().enumerate() {
+            if index > 0 {
+                json.push(',');
+            }
+
+            let _ = write!(json, "{{\"offset\":{},\"size\":{},\"state\":\"{}\"}}", block.offset, block.size, block.state.name());
+        }
+        json.push_str("]}");
+
+        json
+    }
+
+    /// Draw as a self-contained SVG: a strip of blocks, to scale, with a caption below.
+    ///
+    /// Hovering over a block shows its offset, size and state.
+    pub fn to_svg(&self) -> String {
+        let height = SVG_STRIP_HEIGHT + 24;
+        let mut svg = String::new();
+
+        let _ = write!(
+            svg,
+            "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{SVG_WIDTH}\" height=\"{height}\" viewBox=\"0 0 {SVG_WIDTH} {height}\">"
+        );
+
+        for (index, block) in self.blocks.iter().enumerate() {
+            // A block takes up everything up to the next one, header and footer included
+            let end = self.blocks.get(index + 1).map_or(self.heap_size, |next| next.offset);
+            let x = block.offset as f64 * SVG_WIDTH as f64 / self.heap_size as f64;
+            let width = (end - block.offset) as f64 * SVG_WIDTH as f64 / self.heap_size as f64;
+
+            let _ = write!(
+                svg,
+                "<rect x=\"{x:.2}\" y=\"0\" width=\"{width:.2}\" height=\"{SVG_STRIP_HEIGHT}\" fill=\"{}\" stroke=\"#000\" stroke-width=\"0.25\"><title>{} bytes at offset {}, {}</title></rect>",
+                block.state.colour(),
+                block.size,
+                block.offset,
+                block.state.name(),
+            );
+        }
+
+        // Trolled blocks can be handed out again like any other free one
+        let free_blocks = self.blocks.iter().filter(|block| matches!(block.state, BlockState::Free | BlockState::Trolled));
+        let (free_count, free_bytes, largest_free) = free_blocks.fold((0, 0, 0), |(count, bytes, largest), block| {
+            (count + 1, bytes + block.size, largest.max(block.size))
+        });
+
+        let _ = write!(
+            svg,
+            "<text x=\"4\" y=\"{}\" font-family=\"monospace\" font-size=\"12\">{} blocks, {} bytes free in {} blocks, largest free block {} bytes</text>",
+            SVG_STRIP_HEIGHT + 16,
+            self.blocks.len(),
+            free_bytes,
+            free_count,
+            largest_free,
+        );
+        svg.push_str("</svg>");
+
+        svg
+    }
+}
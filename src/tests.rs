@@ -1,16 +1,79 @@
 #[cfg(test)]
+// `mod tests` in `tests.rs`, as it has always been
+#[allow(clippy::module_inception)]
 mod tests {
 
     use core::alloc::{GlobalAlloc, Layout};
 
     use crate::*;
+    // The glob above would otherwise bring in `#[trolloc::test]` in place of the built-in one.
+    use core::prelude::v1::test;
+    use crate::gjallocator;
+    use crate::troll::Troll;
+    use crate::free_list::{FreeList, FreeNode, Linked};
+    use std::vec::Vec;
+
+    /// Bare list node for testing the free list on its own.
+    struct Node {
+        links: FreeNode<Node>,
+    }
+
+    unsafe impl Linked for Node {
+        unsafe fn links(node: *mut Self) -> *mut FreeNode<Self> {
+            core::ptr::addr_of_mut!((*node).links)
+        }
+    }
+
+    fn free_list_nodes<const N: usize>() -> [Node; N] {
+        core::array::from_fn(|_| Node { links: FreeNode::new() })
+    }
+
+    fn free_list_contents(list: &FreeList<Node>) -> Vec<*mut Node> {
+        unsafe { list.iter().collect() }
+    }
+
+    /// Troll with `action` and nothing else, every single time, the same way every run, and
+    /// [`remember`] how.
+    #[cfg(feature = "trolling")]
+    fn only(action: crate::policy::TrollAction) -> crate::policy::TrollPolicy {
+        use crate::policy::{TrollActions, TrollPolicy};
+
+        TrollPolicy {
+            seed: Some(42),
+            probability: Some(1.0),
+            actions: TrollActions::NONE.with(action),
+            log: Some(remember),
+            ..TrollPolicy::DEFAULT
+        }
+    }
+
+    #[cfg(feature = "trolling")]
+    std::thread_local! {
+        /// Every troll [`remember`]ed so far. Each test gets a thread of its own, so these are
+        /// only ever its own.
+        static TROLLS: core::cell::RefCell<Vec<crate::policy::TrollDetail>> = const { core::cell::RefCell::new(Vec::new()) };
+    }
+
+    /// Log hook that keeps every troll's details around for [`trolls`].
+    #[cfg(feature = "trolling")]
+    fn remember(event: &crate::policy::TrollEvent) {
+        TROLLS.with_borrow_mut(|trolls| trolls.push(event.detail));
+    }
+
+    /// Get the details of every troll in this test so far.
+    #[cfg(feature = "trolling")]
+    fn trolls() -> Vec<crate::policy::TrollDetail> {
+        TROLLS.with_borrow(Vec::clone)
+    }
 
     // #[global_allocator]
     // static mut ALLOCATOR: Trollocator = Trollocator::new();
 
     #[test]
+    #[cfg_attr(feature = "trolling", ignore = "trolling frees blocks out from under the test")]
+    #[allow(deprecated, non_snake_case, clippy::assertions_on_constants)]
     fn it_works() {
-        let mut ALLOCATOR = Trollocator::new();
+        let mut ALLOCATOR: Trollocator = Trollocator::new();
         unsafe { ALLOCATOR.heap_init(); }
 
         unsafe {
@@ -36,8 +99,10 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(feature = "trolling", ignore = "trolling frees blocks out from under the test")]
+    #[allow(deprecated, non_snake_case, clippy::assertions_on_constants)]
     fn free_works() {
-        let mut ALLOCATOR = Trollocator::new();
+        let mut ALLOCATOR: Trollocator = Trollocator::new();
         unsafe { ALLOCATOR.heap_init(); }
 
         unsafe {
@@ -68,8 +133,10 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(feature = "trolling", ignore = "trolling frees blocks out from under the test")]
+    #[allow(deprecated, non_snake_case, clippy::assertions_on_constants)]
     fn coalesce_works() {
-        let mut ALLOCATOR = Trollocator::new();
+        let mut ALLOCATOR: Trollocator = Trollocator::new();
         unsafe { ALLOCATOR.heap_init(); }
 
         unsafe {
@@ -100,8 +167,10 @@ mod tests {
     }
 
     #[test]
+    #[cfg_attr(feature = "trolling", ignore = "trolling frees blocks out from under the test")]
+    #[allow(deprecated, non_snake_case, clippy::assertions_on_constants)]
     fn realloc_works() {
-        let mut ALLOCATOR = Trollocator::new();
+        let mut ALLOCATOR: Trollocator = Trollocator::new();
         unsafe { ALLOCATOR.heap_init(); }
 
         unsafe {
@@ -128,4 +197,970 @@ mod tests {
 
         unsafe { ALLOCATOR.heap_destroy(); }
     }
-}
\ No newline at end of file
+
+    #[test]
+    #[cfg_attr(feature = "trolling", ignore = "trolling frees blocks out from under the test")]
+    #[allow(deprecated)]
+    fn small_heap_works() {
+        // Barely more than one block's worth
+        let mut allocator: Trollocator<64> = Trollocator::new();
+        unsafe { allocator.heap_init(); }
+
+        unsafe {
+            let bingus = malloc(&mut allocator, 8);
+            assert!(!bingus.is_null());
+            *bingus = 5u8;
+            assert_eq!(5u8, *bingus);
+            free(&mut allocator, bingus);
+
+            // Nowhere near enough room left for this
+            assert!(malloc(&mut allocator, 64).is_null());
+        }
+
+        unsafe { allocator.heap_destroy(); }
+    }
+
+    #[test]
+    fn region_works() {
+        let region: &'static mut [u8] = std::boxed::Box::leak(vec![0u8; 4096].into_boxed_slice());
+        let (region_start, region_len) = (region.as_ptr() as usize, region.len());
+
+        let allocator = gjallocator::Trollocator::from_region(region);
+        assert!(allocator.heap_start() > region_start);
+        assert!(allocator.heap_end() <= region_start + region_len);
+        assert_eq!(0, allocator.get_alloced_blocks());
+
+        // Blocks come out of the region, and go back into it
+        unsafe {
+            let layout = Layout::from_size_align_unchecked(64, 8);
+            let bingus = allocator.alloc(layout);
+            assert!(!bingus.is_null());
+            assert!(region_start <= bingus as usize && bingus as usize + 64 <= region_start + region_len);
+            assert!(allocator.heap_start() <= bingus as usize && (bingus as usize) < allocator.heap_end());
+
+            core::ptr::write_bytes(bingus, 0xab, 64);
+            assert!(core::slice::from_raw_parts(bingus, 64).iter().all(|&byte| byte == 0xab));
+            assert_eq!(1, allocator.get_alloced_blocks());
+
+            allocator.dealloc(bingus, layout);
+            assert_eq!(0, allocator.get_alloced_blocks());
+            assert_eq!(Ok(()), allocator.check_heap());
+        }
+
+        // Nowhere near enough room for the metadata
+        let tiny = gjallocator::Trollocator::<0>::new();
+        let mut buf = [0u8; 16];
+        assert!(!unsafe { tiny.init(buf.as_mut_ptr(), buf.len()) });
+        assert_eq!(0, tiny.heap_start());
+        assert!(unsafe { tiny.alloc(Layout::from_size_align_unchecked(8, 8)) }.is_null());
+    }
+
+    #[test]
+    fn free_list_push_works() {
+        let mut nodes = free_list_nodes::<3>();
+        let [a, b, c] = nodes.each_mut().map(|node| node as *mut Node);
+        let mut list = FreeList::new();
+        assert!(free_list_contents(&list).is_empty());
+
+        unsafe {
+            list.push_front(a);
+            list.push_front(b);
+            list.push_front(c);
+        }
+
+        // Newest first
+        assert_eq!(vec![c, b, a], free_list_contents(&list));
+    }
+
+    #[test]
+    fn free_list_remove_works() {
+        let mut nodes = free_list_nodes::<4>();
+        let [a, b, c, d] = nodes.each_mut().map(|node| node as *mut Node);
+        let mut list = FreeList::new();
+
+        unsafe {
+            for node in [a, b, c, d] {
+                list.push_front(node);
+            }
+
+            // Middle
+            list.remove(b);
+            assert_eq!(vec![d, c, a], free_list_contents(&list));
+
+            // Head, which used to be left dangling
+            list.remove(d);
+            assert_eq!(vec![c, a], free_list_contents(&list));
+
+            // Tail
+            list.remove(a);
+            assert_eq!(vec![c], free_list_contents(&list));
+
+            // Removed nodes can come back
+            list.push_front(b);
+            assert_eq!(vec![b, c], free_list_contents(&list));
+
+            // Last one out
+            list.remove(c);
+            list.remove(b);
+            assert!(free_list_contents(&list).is_empty());
+        }
+    }
+
+    #[cfg(feature = "nightly")]
+    #[test]
+    fn allocator_api_works() {
+        use crate::policy::{TrollActions, TrollPolicy};
+
+        // Lazy reallocs don't need a second block to troll, so nothing gets to troll here
+        let policy = TrollPolicy { actions: TrollActions::NONE, ..TrollPolicy::DEFAULT };
+        let allocator = gjallocator::Trollocator::<4096>::new().with_policy(policy);
+
+        // Growing a lone block happens in place
+        let mut vec = Vec::new_in(&allocator);
+        for i in 0..256u32 {
+            vec.push(i);
+        }
+
+        let addr = vec.as_ptr() as usize;
+        assert!(allocator.heap_start() <= addr && addr < allocator.heap_end());
+        assert_eq!(1, allocator.get_alloced_blocks());
+
+        vec.truncate(8);
+        vec.shrink_to_fit();
+        assert_eq!(addr, vec.as_ptr() as usize);
+        assert_eq!((0..8).collect::<Vec<u32>>(), vec[..]);
+
+        core::mem::drop(vec);
+        assert_eq!(0, allocator.get_alloced_blocks());
+    }
+
+    #[test]
+    fn troll_wrapper_works() {
+        let allocator: Troll<std::alloc::System, 8> = Troll::new(std::alloc::System);
+
+        unsafe {
+            // A lone allocation is never picked for trolling
+            let layout = Layout::from_size_align_unchecked(4, 4);
+            let bingus = allocator.alloc(layout);
+            assert!(!bingus.is_null());
+            assert_eq!(1, allocator.get_alloced_blocks());
+
+            bingus.copy_from_nonoverlapping([1u8, 2, 3, 4].as_ptr(), 4);
+            let bongus = allocator.realloc(bingus, layout, 64);
+            assert!(!bongus.is_null());
+            assert_eq!(1, allocator.get_alloced_blocks());
+            for i in 1..=4u8 {
+                assert_eq!(i, *bongus.offset(i as isize - 1));
+            }
+
+            allocator.dealloc(bongus, Layout::from_size_align_unchecked(64, 4));
+            assert_eq!(0, allocator.get_alloced_blocks());
+        }
+    }
+
+    #[cfg(not(feature = "trolling"))]
+    #[test]
+    fn nothing_gets_trolled() {
+        let allocator = gjallocator::Trollocator::<{ 64 << 10 }>::new();
+        let wrapper: Troll<std::alloc::System, 256> = Troll::new(std::alloc::System);
+        let layout = Layout::new::<u64>();
+
+        unsafe {
+            let blocks: Vec<*mut u8> = (0..256).map(|_| allocator.alloc(layout)).collect();
+            let wrapped: Vec<*mut u8> = (0..256).map(|_| wrapper.alloc(layout)).collect();
+            assert_eq!(256, allocator.get_alloced_blocks());
+            assert_eq!(256, wrapper.get_alloced_blocks());
+            assert_eq!(Ok(()), allocator.check_heap());
+
+            for (block, wrapped) in blocks.into_iter().zip(wrapped) {
+                allocator.dealloc(block, layout);
+                wrapper.dealloc(wrapped, layout);
+            }
+        }
+
+        assert_eq!(0, allocator.get_alloced_blocks());
+        assert_eq!(0, wrapper.get_alloced_blocks());
+        assert_eq!(Ok(()), allocator.check_heap());
+    }
+
+    #[cfg(all(feature = "alloc", feature = "trolling"))]
+    #[test]
+    fn leftovers_are_not_trolled() {
+        use crate::policy::{TrollAction, TrollActions, TrollPolicy};
+
+        let policy = only(TrollAction::Free);
+        let allocator = gjallocator::Trollocator::<4096>::new().with_policy(policy);
+
+        // Bingus trolls itself, and merges into the free rest of the heap. Bongus is split right back
+        // off it, and what is left over was never anybody's, trolled or otherwise.
+        let bongus = unsafe {
+            let bingus = allocator.alloc(Layout::from_size_align_unchecked(16, 8));
+            allocator.set_policy(TrollPolicy { actions: TrollActions::NONE, ..policy });
+            let bongus = allocator.alloc(Layout::from_size_align_unchecked(16, 8));
+            assert_eq!(bingus, bongus);
+            bongus as usize
+        };
+
+        let report = allocator.leak_report();
+        assert_eq!(vec![bongus], report.leaked().map(|leak| leak.address).collect::<Vec<_>>());
+        assert_eq!(0, report.trolled().count());
+    }
+
+    #[cfg(feature = "trolling")]
+    #[test]
+    fn something_gets_trolled() {
+        let wrapper: Troll<std::alloc::System, 256> = Troll::new(std::alloc::System);
+        let layout = Layout::new::<u64>();
+
+        for _ in 0..256 {
+            unsafe { wrapper.alloc(layout) };
+        }
+
+        // Not a chance that every single coin flip came up tails. Everything is leaked, since
+        // freeing memory that was trolled and handed out again would free it twice. Trolled.
+        assert!(wrapper.get_alloced_blocks() < 256);
+    }
+
+    #[cfg(feature = "trolling")]
+    #[test]
+    fn troll_wrapper_follows_policy() {
+        use crate::policy::{TrollAction, TrollActions, TrollPolicy};
+
+        let layout = Layout::new::<u64>();
+        let policy = only(TrollAction::Free);
+
+        // Every allocation trolls one, which with one live allocation at a time is itself
+        let wrapper: Troll<std::alloc::System, 64> = Troll::with_policy(std::alloc::System, policy);
+        for _ in 0..32 {
+            unsafe { wrapper.alloc(layout) };
+            assert_eq!(0, wrapper.get_alloced_blocks());
+        }
+
+        // Nothing but freeing is up to the wrapper, and freeing is not allowed
+        wrapper.set_policy(TrollPolicy { actions: TrollActions::NONE.with(TrollAction::BitFlip), ..policy });
+        let blocks: Vec<*mut u8> = (0..32).map(|_| unsafe { wrapper.alloc(layout) }).collect();
+        assert_eq!(32, wrapper.get_alloced_blocks());
+
+        // Never means never
+        wrapper.set_policy(TrollPolicy { probability: Some(0.0), ..policy });
+        let more: Vec<*mut u8> = (0..32).map(|_| unsafe { wrapper.alloc(layout) }).collect();
+        assert_eq!(64, wrapper.get_alloced_blocks());
+
+        for block in blocks.into_iter().chain(more) {
+            unsafe { wrapper.dealloc(block, layout) };
+        }
+        assert_eq!(0, wrapper.get_alloced_blocks());
+    }
+
+    #[test]
+    fn realloc_stays_in_place() {
+        use crate::policy::{TrollActions, TrollPolicy};
+
+        let policy = TrollPolicy { actions: TrollActions::NONE, ..TrollPolicy::DEFAULT };
+        let allocator = gjallocator::Trollocator::<4096>::new().with_policy(policy);
+
+        unsafe {
+            let layout = Layout::from_size_align_unchecked(64, 8);
+            let bingus = allocator.alloc(layout);
+            let bongus = allocator.alloc(layout);
+            let _guard = allocator.alloc(layout);
+            for i in 0..64 {
+                *bingus.add(i) = i as u8;
+            }
+
+            // Shrinking just gives the tail back
+            let shrunk = allocator.realloc(bingus, layout, 16);
+            assert_eq!(bingus, shrunk);
+            assert_eq!(Ok(()), allocator.check_heap());
+
+            // Growing takes over the free neighbour, all of it and then some
+            allocator.dealloc(bongus, layout);
+            let grown = allocator.realloc(shrunk, Layout::from_size_align_unchecked(16, 8), 120);
+            assert_eq!(bingus, grown);
+            assert!(allocator.usable_size(grown) >= 120);
+            assert_eq!((0..16).collect::<Vec<u8>>(), core::slice::from_raw_parts(grown, 16));
+            assert_eq!(Ok(()), allocator.check_heap());
+        }
+    }
+
+    #[cfg(not(feature = "trolling"))]
+    #[test]
+    fn frees_coalesce_backwards() {
+        let allocator = gjallocator::Trollocator::<4096>::new();
+
+        unsafe {
+            let layout = Layout::from_size_align_unchecked(32, 8);
+            let [a, b, c, _guard] = [(); 4].map(|_| allocator.alloc(layout));
+            // Whatever a block costs on top of its payload
+            let overhead = b as usize - a as usize - 32;
+
+            // Each one merges into the free block before it, never with the one after, since
+            // that one is still allocated. Which leaves the merged block and the rest of the heap.
+            for block in [a, b, c] {
+                allocator.dealloc(block, layout);
+                assert_eq!(Ok(()), allocator.check_heap());
+                #[cfg(feature = "stats")]
+                assert_eq!(2, allocator.stats().free_blocks);
+            }
+
+            // All three are one block now, starting where the first one did
+            let merged = allocator.alloc(Layout::from_size_align_unchecked(3 * 32 + 2 * overhead, 8));
+            assert_eq!(a, merged);
+            assert_eq!(Ok(()), allocator.check_heap());
+        }
+    }
+
+    #[cfg(not(feature = "trolling"))]
+    #[test]
+    fn check_heap_works() {
+        let allocator = gjallocator::Trollocator::<4096>::new();
+        assert_eq!(Ok(()), allocator.check_heap());
+
+        unsafe {
+            let bingus = allocator.alloc(Layout::from_size_align_unchecked(16, 8));
+            let bongus = allocator.alloc(Layout::from_size_align_unchecked(100, 8));
+            let _wingus = allocator.alloc(Layout::from_size_align_unchecked(8, 64));
+            allocator.dealloc(bingus, Layout::from_size_align_unchecked(16, 8));
+            let bongus = allocator.realloc(bongus, Layout::from_size_align_unchecked(100, 8), 40);
+            assert_eq!(Ok(()), allocator.check_heap());
+
+            // Scribble over the footer, like an off-by-one would
+            let footer = bongus.add(allocator.usable_size(bongus)) as *mut usize;
+            *footer = 0xdeadbeef;
+            assert_eq!(Err("block header and footer disagree"), allocator.check_heap());
+        }
+    }
+
+    #[cfg(all(feature = "debug-checks", not(feature = "trolling")))]
+    #[test]
+    #[should_panic(expected = "trolloc heap is corrupted")]
+    fn debug_checks_catch_corruption() {
+        let allocator = gjallocator::Trollocator::<4096>::new();
+        let layout = Layout::new::<u64>();
+
+        unsafe {
+            let bingus = allocator.alloc(layout);
+            *(bingus.add(allocator.usable_size(bingus)) as *mut usize) = 0xdeadbeef;
+            allocator.alloc(layout);
+        }
+    }
+
+    #[cfg(all(feature = "stats", not(feature = "trolling")))]
+    #[test]
+    fn stats_work() {
+        let allocator = gjallocator::Trollocator::<4096>::new();
+        assert_eq!(gjallocator::Stats::default(), allocator.stats());
+
+        unsafe {
+            let bingus = allocator.alloc(Layout::from_size_align_unchecked(16, 8));
+            let bongus = allocator.alloc(Layout::from_size_align_unchecked(100, 8));
+            let stats = allocator.stats();
+            assert_eq!(16 + 104, stats.bytes_in_use);
+            assert_eq!(2, stats.allocs);
+            assert_eq!(1, stats.free_blocks);
+            assert_eq!(stats.bytes_free, stats.largest_free_block);
+            assert_eq!(0.0, stats.fragmentation);
+
+            // Leave a hole in front of bongus
+            allocator.dealloc(bingus, Layout::from_size_align_unchecked(16, 8));
+            let stats = allocator.stats();
+            assert_eq!(104, stats.bytes_in_use);
+            assert_eq!(120, stats.peak_bytes_in_use);
+            assert_eq!(1, stats.frees);
+            assert_eq!(2, stats.free_blocks);
+            assert_eq!(16 + stats.largest_free_block, stats.bytes_free);
+            assert!(stats.fragmentation > 0.0);
+
+            // Shrinking gives the tail back to the big free block
+            let bongus = allocator.realloc(bongus, Layout::from_size_align_unchecked(100, 8), 16);
+            let stats = allocator.stats();
+            assert_eq!(1, stats.reallocs);
+            assert_eq!(16, stats.bytes_in_use);
+            assert_eq!(2, stats.free_blocks);
+            assert_eq!(Ok(()), allocator.check_heap());
+
+            allocator.dealloc(bongus, Layout::from_size_align_unchecked(16, 8));
+            let stats = allocator.stats();
+            assert_eq!(0, stats.bytes_in_use);
+            assert_eq!(1, stats.free_blocks);
+            assert_eq!(stats.bytes_free, stats.largest_free_block);
+            assert_eq!(0, stats.trolls);
+        }
+    }
+
+    #[cfg(all(feature = "alloc", not(feature = "trolling")))]
+    #[test]
+    fn snapshot_works() {
+        use crate::snapshot::{BlockSnapshot, BlockState};
+
+        let allocator = gjallocator::Trollocator::<4096>::new();
+        assert!(allocator.snapshot().blocks.is_empty());
+
+        unsafe {
+            let _bingus = allocator.alloc(Layout::from_size_align_unchecked(16, 8));
+            let bongus = allocator.alloc(Layout::from_size_align_unchecked(100, 8));
+            let _wingus = allocator.alloc(Layout::from_size_align_unchecked(16, 8));
+            allocator.dealloc(bongus, Layout::from_size_align_unchecked(100, 8));
+        }
+
+        // Every block takes up 24 more bytes than its size, for the header and footer
+        let snapshot = allocator.snapshot();
+        assert_eq!(
+            vec![
+                BlockSnapshot { offset: 0, size: 16, state: BlockState::Allocated },
+                BlockSnapshot { offset: 40, size: 104, state: BlockState::Free },
+                BlockSnapshot { offset: 168, size: 16, state: BlockState::Allocated },
+                BlockSnapshot { offset: 208, size: snapshot.heap_size - 232, state: BlockState::Free },
+            ],
+            snapshot.blocks
+        );
+
+        let json = snapshot.to_json();
+        assert!(json.starts_with(&format!("{{\"heap_size\":{},\"blocks\":[{{\"offset\":0,\"size\":16,\"state\":\"allocated\"}},", snapshot.heap_size)));
+        assert!(json.ends_with("\"state\":\"free\"}]}"));
+
+        let svg = snapshot.to_svg();
+        assert!(svg.starts_with("<svg xmlns=\"http://www.w3.org/2000/svg\""));
+        assert!(svg.ends_with("</svg>"));
+        assert_eq!(4, svg.matches("<rect").count());
+        assert!(svg.contains("4 blocks"));
+    }
+
+    #[test]
+    fn troll_actions_parse() {
+        use crate::policy::{TrollAction, TrollActions, TrollPolicy};
+
+        assert_eq!(Ok(TrollActions::NONE.with(TrollAction::Free)), TrollActions::from_names(" free, "));
+        assert_eq!(Ok(TrollActions::ALL), TrollActions::from_names("all"));
+        assert_eq!(Ok(TrollActions::NONE), TrollActions::from_names("none"));
+        assert_eq!(Ok(TrollActions::NONE), TrollActions::from_names(""));
+        assert!(TrollActions::from_names("free,bingus").is_err());
+
+        let policy = TrollPolicy::from_env(|name| match name {
+            "TROLLOC_SEED" => Some("42"),
+            "TROLLOC_PROBABILITY" => Some("0.25"),
+            _ => None,
+        });
+        let policy = policy.unwrap();
+        assert_eq!(Some(42), policy.seed);
+        assert_eq!(Some(0.25), policy.probability);
+        // Classic trolling, unless asked for more
+        assert_eq!(TrollActions::NONE.with(TrollAction::Free), policy.actions);
+
+        assert!(TrollPolicy::from_env(|name| (name == "TROLLOC_PROBABILITY").then_some("1.5")).is_err());
+        assert!(TrollPolicy::from_env(|name| (name == "TROLLOC_SEED").then_some("bingus")).is_err());
+    }
+
+    #[cfg(feature = "trolling")]
+    #[test]
+    fn policy_is_deterministic() {
+        use crate::policy::TrollPolicy;
+
+        // Live allocation counts after every allocation, which trolling keeps knocking down
+        fn live_counts(policy: TrollPolicy) -> Vec<usize> {
+            let allocator = gjallocator::Trollocator::<{ 64 << 10 }>::new().with_policy(policy);
+            (0..100)
+                .map(|_| unsafe {
+                    allocator.alloc(Layout::from_size_align_unchecked(16, 8));
+                    allocator.get_alloced_blocks()
+                })
+                .collect()
+        }
+
+        let policy = TrollPolicy { seed: Some(1337), probability: Some(0.5), ..TrollPolicy::DEFAULT };
+        let counts = live_counts(policy);
+        assert_eq!(counts, live_counts(policy));
+        assert!(*counts.last().unwrap() < 100);
+
+        // Never means never
+        let counts = live_counts(TrollPolicy { probability: Some(0.0), ..policy });
+        assert_eq!((1..=100).collect::<Vec<_>>(), counts);
+    }
+
+    #[cfg(feature = "std")]
+    #[crate::test(seed = 7, runs = 4)]
+    fn harness_runs_trials(allocator: &gjallocator::Trollocator<0>) {
+        unsafe {
+            for size in 1..64 {
+                allocator.alloc(Layout::from_size_align_unchecked(size, 8));
+            }
+        }
+        assert_eq!(Ok(()), allocator.check_heap());
+    }
+
+    #[cfg(feature = "std")]
+    #[crate::test(runs = 3)]
+    #[should_panic(expected = "3 of 3 runs failed")]
+    fn harness_reports_failures(_allocator: &gjallocator::Trollocator<0>) {
+        panic!("bingus");
+    }
+
+    #[cfg(not(feature = "trolling"))]
+    #[test]
+    fn reset_works() {
+        let allocator = gjallocator::Trollocator::<4096>::new();
+
+        unsafe {
+            // Nothing to reset yet
+            allocator.reset();
+            allocator.assert_no_live_allocations();
+
+            let bingus = allocator.alloc(Layout::from_size_align_unchecked(16, 8));
+            let _bongus = allocator.alloc(Layout::from_size_align_unchecked(100, 8));
+            allocator.dealloc(bingus, Layout::from_size_align_unchecked(16, 8));
+
+            let leaked = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| allocator.assert_no_live_allocations()));
+            assert!(leaked.is_err());
+
+            allocator.reset();
+            allocator.assert_no_live_allocations();
+            assert_eq!(Ok(()), allocator.check_heap());
+
+            // Back to square one, so the first allocation lands in the same place again
+            let wingus = allocator.alloc(Layout::from_size_align_unchecked(16, 8));
+            assert_eq!(bingus, wingus);
+        }
+    }
+
+    #[cfg(all(feature = "alloc", not(feature = "trolling")))]
+    #[test]
+    fn leak_report_works() {
+        use crate::leaks::Leak;
+
+        let allocator = gjallocator::Trollocator::<4096>::new();
+        assert!(allocator.leak_report().leaks.is_empty());
+
+        let (bingus, wingus) = unsafe {
+            let bingus = allocator.alloc(Layout::from_size_align_unchecked(16, 8));
+            let bongus = allocator.alloc(Layout::from_size_align_unchecked(100, 8));
+            let wingus = allocator.alloc(Layout::from_size_align_unchecked(32, 8));
+            allocator.dealloc(bongus, Layout::from_size_align_unchecked(100, 8));
+            (bingus as usize, wingus as usize)
+        };
+
+        let report = allocator.leak_report();
+        assert_eq!(
+            vec![
+                Leak { address: bingus, size: 16, seq: 0, trolled: false },
+                Leak { address: wingus, size: 32, seq: 2, trolled: false },
+            ],
+            report.leaks
+        );
+        assert!(!report.is_empty());
+        assert_eq!(0, report.trolled().count());
+        assert_eq!(
+            format!("2 blocks leaked, 48 bytes\n  #0: 16 bytes at {bingus:#x}\n  #2: 32 bytes at {wingus:#x}\n0 blocks trolled, freed too early or not at all, 0 bytes\n"),
+            format!("{report}")
+        );
+    }
+
+    #[cfg(feature = "trolling")]
+    #[test]
+    fn bit_flips_stay_in_payloads() {
+        use crate::policy::TrollAction;
+
+        let allocator = gjallocator::Trollocator::<{ 64 << 10 }>::new().with_policy(only(TrollAction::BitFlip));
+
+        // Every allocation flips bits in one of the zeroed blocks, possibly the one it just made
+        let blocks: Vec<*mut u8> = (0..32)
+            .map(|_| unsafe {
+                let block = allocator.alloc(Layout::from_size_align_unchecked(64, 8));
+                core::ptr::write_bytes(block, 0, 64);
+                block
+            })
+            .collect();
+
+        // Nothing got freed, and the headers and footers made it through
+        assert_eq!(32, allocator.get_alloced_blocks());
+        assert_eq!(Ok(()), allocator.check_heap());
+
+        let flipped: u32 = blocks
+            .iter()
+            .map(|&block| unsafe { core::slice::from_raw_parts(block, 64) }.iter().map(|byte| byte.count_ones()).sum::<u32>())
+            .sum();
+        assert!(flipped > 0);
+    }
+
+    #[cfg(feature = "trolling")]
+    #[test]
+    fn time_bombs_go_off_later() {
+        use crate::policy::{TrollAction, TrollDetail};
+
+        let armed = || trolls().iter().filter(|detail| matches!(detail, TrollDetail::Armed { .. })).count();
+        let detonated = || trolls().iter().filter(|detail| matches!(detail, TrollDetail::Detonated)).count();
+
+        let allocator = gjallocator::Trollocator::<{ 64 << 10 }>::new().with_policy(only(TrollAction::TimeBomb));
+
+        unsafe {
+            // Nothing goes off straight away
+            allocator.alloc(Layout::from_size_align_unchecked(16, 8));
+            assert_eq!(1, armed());
+            assert_eq!(1, allocator.get_alloced_blocks());
+
+            for _ in 1..200 {
+                allocator.alloc(Layout::from_size_align_unchecked(16, 8));
+            }
+        }
+
+        // The ones counting allocations had plenty of time to go off, the rest are still ticking
+        let detonated = detonated();
+        assert!(detonated > 0);
+        assert!(detonated < armed());
+        assert_eq!(200 - detonated, allocator.get_alloced_blocks());
+        assert_eq!(Ok(()), allocator.check_heap());
+    }
+
+    #[cfg(feature = "trolling")]
+    #[test]
+    fn undersized_allocations_come_up_short() {
+        use crate::policy::{TrollAction, TrollActions, TrollPolicy};
+
+        assert!(!TrollActions::DEFAULT.contains(TrollAction::Undersize));
+
+        let allocator = gjallocator::Trollocator::<4096>::new().with_policy(TrollPolicy { undersize: 8, ..only(TrollAction::Undersize) });
+
+        unsafe {
+            let bingus = allocator.alloc(Layout::from_size_align_unchecked(64, 8));
+            let bongus = allocator.alloc(Layout::from_size_align_unchecked(64, 8));
+            assert_eq!(56, allocator.usable_size(bingus));
+
+            // Right up against each other, with only the footer and the header in between. Headers
+            // only have room for a sequence number with leak reports.
+            let overhead = if cfg!(feature = "alloc") { 24 } else { 16 };
+            assert_eq!(bingus as usize + 56 + overhead, bongus as usize);
+            assert_eq!(Ok(()), allocator.check_heap());
+
+            // Zeroed ones are never short, or zeroing them would break the heap all by itself
+            let wingus = allocator.alloc_zeroed(Layout::from_size_align_unchecked(64, 8));
+            assert_eq!(64, allocator.usable_size(wingus));
+            assert_eq!(Ok(()), allocator.check_heap());
+
+            // Which is not enough to keep the neighbour safe
+            core::ptr::write_bytes(bingus, 0xff, 64);
+            assert!(allocator.check_heap().is_err());
+        }
+    }
+
+    #[cfg(feature = "trolling")]
+    #[test]
+    fn misaligned_allocations_still_free() {
+        use crate::policy::{TrollAction, TrollActions};
+
+        // Opt in, so none of it unless asked for
+        assert!(!TrollActions::DEFAULT.contains(TrollAction::Misalign));
+        assert!(TrollActions::ALL.contains(TrollAction::Misalign));
+
+        let allocator = gjallocator::Trollocator::<4096>::new().with_policy(only(TrollAction::Misalign));
+
+        unsafe {
+            for align in [2, 4, 8] {
+                let layout = Layout::from_size_align_unchecked(32, align);
+                let bingus = allocator.alloc(layout);
+                assert_ne!(0, bingus as usize % align);
+                assert!(allocator.usable_size(bingus) >= 32);
+
+                // Crooked or not, every byte asked for is there
+                core::ptr::write_bytes(bingus, 0xff, 32);
+                assert_eq!(Ok(()), allocator.check_heap());
+
+                let bingus = allocator.realloc(bingus, layout, 128);
+                core::ptr::write_bytes(bingus, 0xff, 128);
+                assert_eq!(Ok(()), allocator.check_heap());
+
+                allocator.dealloc(bingus, Layout::from_size_align_unchecked(128, align));
+            }
+
+            // Bytes have nothing to be misaligned from
+            let bongus = allocator.alloc(Layout::from_size_align_unchecked(32, 1));
+            assert_eq!(0, bongus as usize % 8);
+            allocator.dealloc(bongus, Layout::from_size_align_unchecked(32, 1));
+
+            assert_eq!(Ok(()), allocator.check_heap());
+        }
+    }
+
+    #[cfg(feature = "trolling")]
+    #[test]
+    fn lazy_reallocs_cut_corners() {
+        use crate::policy::{TrollAction, TrollDetail};
+
+        let allocator = gjallocator::Trollocator::<{ 64 << 10 }>::new().with_policy(only(TrollAction::LazyRealloc));
+
+        unsafe {
+            for _ in 0..100 {
+                let bingus = allocator.alloc(Layout::from_size_align_unchecked(16, 8));
+                core::ptr::write_bytes(bingus, 0xff, 16);
+
+                let bongus = allocator.realloc(bingus, Layout::from_size_align_unchecked(16, 8), 256);
+                assert!(!bongus.is_null());
+
+                // Never grown means the same block, and it is still too small
+                if bongus == bingus {
+                    assert!(allocator.usable_size(bongus) < 256);
+                }
+
+                allocator.dealloc(bongus, Layout::from_size_align_unchecked(256, 8));
+            }
+        }
+
+        // Every realloc was lazy one way or another, and the ones that kept the old block leaked it
+        let trolls = trolls();
+        let partial = trolls.iter().filter(|detail| matches!(detail, TrollDetail::PartialCopy { .. })).count();
+        let not_grown = trolls.iter().filter(|detail| matches!(detail, TrollDetail::NotGrown { .. })).count();
+        let kept = trolls.iter().filter(|detail| matches!(detail, TrollDetail::OldKept { .. })).count();
+        assert!(partial > 0 && not_grown > 0 && kept > 0);
+        assert_eq!(100, partial + not_grown + kept);
+        assert_eq!(kept, allocator.get_alloced_blocks());
+        assert_eq!(Ok(()), allocator.check_heap());
+
+        // Nobody asked for them to stay, so it is not on them
+        #[cfg(feature = "alloc")]
+        {
+            let report = allocator.leak_report();
+            assert!(report.is_empty());
+            assert_eq!(kept, report.trolled().count());
+        }
+    }
+
+    #[cfg(all(feature = "trolling", feature = "nightly"))]
+    #[test]
+    fn lazy_reallocs_grow_zeroed() {
+        use core::alloc::Allocator;
+        use crate::policy::{TrollAction, TrollPolicy};
+
+        let policy = TrollPolicy { seed: Some(3), ..only(TrollAction::LazyRealloc) };
+        let allocator = gjallocator::Trollocator::<{ 64 << 10 }>::new().with_policy(policy);
+
+        // Never grown or not, zeroing whatever did grow must leave the heap alone
+        unsafe {
+            for _ in 0..100 {
+                let old = Layout::from_size_align_unchecked(16, 8);
+                let bingus = allocator.allocate(old).unwrap().cast::<u8>();
+                let bongus = allocator.grow_zeroed(bingus, old, Layout::from_size_align_unchecked(256, 8)).unwrap();
+                assert_eq!(Ok(()), allocator.check_heap());
+                allocator.deallocate(bongus.cast(), Layout::from_size_align_unchecked(256, 8));
+            }
+        }
+    }
+
+    #[cfg(feature = "trolling")]
+    #[test]
+    fn allocator_writes_are_not_trolled() {
+        use crate::policy::{TrollAction, TrollPolicy};
+
+        // Whatever gets freed out from under the caller, it is never the block the allocator
+        // itself is zeroing, copying into or out of, or freeing
+        for seed in 0..50 {
+            let policy = TrollPolicy { seed: Some(seed), probability: Some(0.5), ..only(TrollAction::Free) };
+            let allocator = gjallocator::Trollocator::<{ 64 << 10 }>::new().with_policy(policy);
+
+            unsafe {
+                for i in 0..20 {
+                    let size = 16 + i % 4 * 16;
+                    let bingus = allocator.alloc_zeroed(Layout::from_size_align_unchecked(size, 8));
+                    assert!(core::slice::from_raw_parts(bingus, size).iter().all(|&byte| byte == 0));
+                    // Left for later ones to troll, nobody touches it again
+                    allocator.realloc(bingus, Layout::from_size_align_unchecked(size, 8), 256);
+                    assert_eq!(Ok(()), allocator.check_heap());
+                }
+            }
+        }
+    }
+
+    #[cfg(feature = "trolling")]
+    #[test]
+    fn unzeroed_allocations_have_leftovers() {
+        use crate::policy::{TrollAction, TrollDetail};
+
+        let allocator = gjallocator::Trollocator::<4096>::new().with_policy(only(TrollAction::Unzeroed));
+        unsafe {
+            let layout = Layout::from_size_align_unchecked(64, 8);
+
+            // Someone's secrets, freed without being wiped
+            let bingus = allocator.alloc(layout);
+            core::ptr::write_bytes(bingus, 0x42, 64);
+            allocator.dealloc(bingus, layout);
+
+            let bongus = allocator.alloc_zeroed(layout);
+            assert_eq!(bingus, bongus);
+
+            let bytes = core::slice::from_raw_parts(bongus, 64);
+            let [TrollDetail::Stale { offset, len }] = trolls()[..] else { panic!("not unzeroed exactly once") };
+            assert!(len > 0);
+            assert!(bytes[offset..offset + len].iter().all(|&byte| byte == 0x42));
+            assert!(bytes[..offset].iter().chain(&bytes[offset + len..]).all(|&byte| byte == 0));
+        }
+    }
+
+    #[cfg(feature = "trolling")]
+    #[test]
+    fn aliases_overlap_without_breaking_the_heap() {
+        use crate::policy::{TrollAction, TrollActions};
+
+        assert!(!TrollActions::DEFAULT.contains(TrollAction::Alias));
+
+        let allocator = gjallocator::Trollocator::<4096>::new().with_policy(only(TrollAction::Alias));
+
+        unsafe {
+            // Nothing live to alias yet, so a block of its own
+            let bingus = allocator.alloc(Layout::from_size_align_unchecked(128, 8));
+            core::ptr::write_bytes(bingus, 0, 128);
+
+            let bongus = allocator.alloc(Layout::from_size_align_unchecked(16, 8));
+            assert!((bingus as usize..bingus as usize + 128).contains(&(bongus as usize)));
+            assert_eq!(16, allocator.usable_size(bongus));
+            assert_eq!(1, allocator.get_alloced_blocks());
+
+            // Two owners, one payload
+            core::ptr::write_bytes(bongus, 0xff, 16);
+            assert_eq!(0xff, *bingus.add(bongus as usize - bingus as usize));
+
+            // Freeing the alias leaves the block it aliases alone
+            allocator.dealloc(bongus, Layout::from_size_align_unchecked(16, 8));
+            assert_eq!(1, allocator.get_alloced_blocks());
+            assert_eq!(Ok(()), allocator.check_heap());
+
+            allocator.dealloc(bingus, Layout::from_size_align_unchecked(128, 8));
+            assert_eq!(0, allocator.get_alloced_blocks());
+            assert_eq!(Ok(()), allocator.check_heap());
+        }
+    }
+
+    #[cfg(feature = "trolling")]
+    #[test]
+    fn swaps_keep_the_heap_consistent() {
+        use crate::policy::{TrollAction, TrollActions, TrollPolicy};
+
+        let allocator = gjallocator::Trollocator::<{ 16 << 10 }>::new().with_policy(TrollPolicy {
+            actions: TrollActions::NONE,
+            ..TrollPolicy::DEFAULT
+        });
+
+        unsafe {
+            // Eight blocks of the same size, each full of its own index
+            let blocks: Vec<*mut u8> = (0..8u8)
+                .map(|index| {
+                    let block = allocator.alloc(Layout::from_size_align_unchecked(32, 8));
+                    core::ptr::write_bytes(block, index, 32);
+                    block
+                })
+                .collect();
+
+            allocator.set_policy(only(TrollAction::Swap));
+            for _ in 0..16 {
+                allocator.alloc(Layout::from_size_align_unchecked(256, 8));
+            }
+
+            // Shuffled around, but every block still holds exactly one of them
+            let mut contents: Vec<u8> = blocks
+                .iter()
+                .map(|&block| {
+                    let bytes = core::slice::from_raw_parts(block, 32);
+                    assert!(bytes.iter().all(|&byte| byte == bytes[0]));
+                    bytes[0]
+                })
+                .collect();
+            assert_ne!((0..8).collect::<Vec<u8>>(), contents);
+            contents.sort();
+            assert_eq!((0..8).collect::<Vec<u8>>(), contents);
+
+            assert_eq!(24, allocator.get_alloced_blocks());
+            assert_eq!(Ok(()), allocator.check_heap());
+        }
+    }
+
+    #[cfg(feature = "trolling")]
+    #[test]
+    fn swallowed_frees_leak() {
+        use crate::policy::TrollAction;
+
+        let allocator = gjallocator::Trollocator::<4096>::new().with_policy(only(TrollAction::Leak));
+
+        unsafe {
+            for _ in 0..10 {
+                let bingus = allocator.alloc(Layout::from_size_align_unchecked(64, 8));
+                allocator.dealloc(bingus, Layout::from_size_align_unchecked(64, 8));
+            }
+        }
+
+        // Freed ten times, and all ten are still there
+        assert_eq!(10, allocator.get_alloced_blocks());
+        assert_eq!(Ok(()), allocator.check_heap());
+
+        #[cfg(feature = "stats")]
+        {
+            let stats = allocator.stats();
+            assert_eq!(10, stats.swallowed_frees);
+            assert_eq!(0, stats.frees);
+        }
+
+        // Their owners did give them back, so it is not on them
+        #[cfg(feature = "alloc")]
+        {
+            let report = allocator.leak_report();
+            assert!(report.is_empty());
+            assert_eq!(10, report.trolled().count());
+        }
+    }
+
+    #[cfg(feature = "trolling")]
+    #[test]
+    fn swallowed_frees_give_back_aliases() {
+        use crate::policy::{TrollAction, TrollPolicy};
+
+        let policy = only(TrollAction::Alias);
+        let policy = TrollPolicy { actions: policy.actions.with(TrollAction::Leak), ..policy };
+        let allocator = gjallocator::Trollocator::<4096>::new().with_policy(policy);
+
+        unsafe {
+            let bingus = allocator.alloc(Layout::from_size_align_unchecked(128, 8));
+
+            // Way more aliases than there are slots for, which only works if swallowing their
+            // frees still gives the slots back
+            for _ in 0..32 {
+                let bongus = allocator.alloc(Layout::from_size_align_unchecked(16, 8));
+                assert!((bingus as usize..bingus as usize + 128).contains(&(bongus as usize)));
+                allocator.dealloc(bongus, Layout::from_size_align_unchecked(16, 8));
+            }
+
+            assert_eq!(1, allocator.get_alloced_blocks());
+            assert_eq!(Ok(()), allocator.check_heap());
+        }
+    }
+
+    #[cfg(feature = "trolling")]
+    #[test]
+    fn stalls_take_their_time() {
+        use crate::policy::{TrollAction, TrollActions, TrollDetail, TrollPolicy};
+
+        assert!(!TrollActions::DEFAULT.contains(TrollAction::Stall));
+
+        let allocator = gjallocator::Trollocator::<4096>::new().with_policy(TrollPolicy { stall: 100, ..only(TrollAction::Stall) });
+
+        let start = std::time::Instant::now();
+        unsafe {
+            for _ in 0..10 {
+                let bingus = allocator.alloc(Layout::from_size_align_unchecked(64, 8));
+                allocator.dealloc(bingus, Layout::from_size_align_unchecked(64, 8));
+            }
+        }
+
+        let stalled: Vec<u64> = trolls()
+            .iter()
+            .filter_map(|detail| match *detail {
+                TrollDetail::Stalled { micros } => Some(micros),
+                _ => None,
+            })
+            .collect();
+        assert!(stalled.iter().all(|micros| (1..=100).contains(micros)));
+
+        // At least as long as the stalls say, sleeping never comes up short. Spinning might.
+        if cfg!(feature = "std") {
+            assert!(start.elapsed().as_micros() as u64 >= stalled.iter().sum::<u64>());
+        }
+        assert_eq!(Ok(()), allocator.check_heap());
+
+        #[cfg(feature = "stats")]
+        assert_eq!(20, allocator.stats().stalls.iter().sum::<usize>());
+    }
+}
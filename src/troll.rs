@@ -0,0 +1,294 @@
+//! # Troll
+//!
+//! Trolling as a layer on top of any other [`GlobalAlloc`](core::alloc::GlobalAlloc).
+//!
+//! The [`gjallocator`](crate::gjallocator) fuses trolling into its own, horribly unoptimized,
+//! heap. [`Troll`] instead hands every request to an inner allocator and keeps a side table of
+//! the live allocations, so that it can prematurely free them with the same
+//! [`wyrand`](crate::wyrand)-driven algorithm. This lets programs that need a production-quality
+//! heap get trolled too:
+//!
+//! ```no_run
+//! use std::alloc::System;
+//! use trolloc::troll::Troll;
+//!
+//! #[global_allocator]
+//! static ALLOCATOR: Troll<System> = Troll::new(System);
+//! ```
+//!
+//! A trolled allocation is really freed by the inner allocator, which is free to hand its memory
+//! out again. The side table remembers it until its owner gets around to freeing it, so that the
+//! owner's free does not reach the inner allocator twice... unless the memory was handed out
+//! again in the meantime, in which case the owner frees somebody else's allocation. Trolled.
+//!
+//! The side table has a fixed number of slots, since it cannot allocate. Allocations that do not
+//! fit in it are passed through untracked, and cannot be trolled. Finding an allocation in it
+//! again is a walk over every live one, so it is not fast either, but the inner allocator is
+//! only ever called with the side table unlocked, so at least it does not hold anyone up.
+//!
+//! How often to troll is up to a [`TrollPolicy`], see [`Troll::with_policy`] and [`Troll::set_policy`]. The wrapper never
+//! sees inside the inner allocator's blocks, so [`TrollAction::Free`] is all it can do: a policy
+//! without it trolls nothing. Like everything else, [`Troll`] only trolls with the `trolling`
+//! feature.
+
+use core::{alloc::{GlobalAlloc, Layout}, cell::UnsafeCell, hint, sync::atomic::{AtomicBool, Ordering}};
+
+use crate::policy::{TrollAction, TrollDetail, TrollPolicy, Troller};
+
+/// Number of side table slots of a [`Troll`] when none is given.
+pub const DEFAULT_SLOTS: usize = 4096;
+
+#[derive(Clone, Copy)]
+/// An allocation remembered in the side table.
+struct Allocation {
+    /// Pointer handed out by the inner allocator.
+    ptr: *mut u8,
+    /// Size it was requested with.
+    size: usize,
+    /// Alignment it was requested with.
+    align: usize,
+}
+
+impl Allocation {
+    /// Unused slot.
+    const EMPTY: Self = Self { ptr: core::ptr::null_mut(), size: 0, align: 1 };
+
+    /// Get back the layout the allocation was requested with.
+    fn layout(&self) -> Layout {
+        // SAFETY: size and align came out of a valid layout.
+        unsafe { Layout::from_size_align_unchecked(self.size, self.align) }
+    }
+}
+
+/// Densely packed, fixed capacity set of allocations.
+struct Table<const SLOTS: usize> {
+    /// Allocations, the first `len` of which are in use.
+    slots: [Allocation; SLOTS],
+    /// Number of slots in use.
+    len: usize,
+}
+
+impl<const SLOTS: usize> Table<SLOTS> {
+    /// Empty table.
+    const fn new() -> Self {
+        Self { slots: [Allocation::EMPTY; SLOTS], len: 0 }
+    }
+
+    /// Remember an allocation. Returns `false` if the table is full.
+    fn insert(&mut self, allocation: Allocation) -> bool {
+        if self.len == SLOTS {
+            return false;
+        }
+
+        self.slots[self.len] = allocation;
+        self.len += 1;
+        true
+    }
+
+    /// Find the slot holding `ptr`.
+    fn position(&self, ptr: *mut u8) -> Option<usize> {
+        self.slots[..self.len].iter().position(|allocation| allocation.ptr == ptr)
+    }
+
+    /// Forget the allocation in a slot, moving the last one into its place.
+    fn swap_remove(&mut self, index: usize) -> Allocation {
+        let allocation = self.slots[index];
+        self.len -= 1;
+        self.slots[index] = self.slots[self.len];
+        self.slots[self.len] = Allocation::EMPTY;
+        allocation
+    }
+
+    /// Forget the allocation at `ptr`, if it is in the table.
+    fn remove(&mut self, ptr: *mut u8) -> Option<Allocation> {
+        self.position(ptr).map(|index| self.swap_remove(index))
+    }
+}
+
+/// Side table of a [`Troll`].
+struct SideTable<const SLOTS: usize> {
+    /// Allocations that are live as far as their owners know.
+    live: Table<SLOTS>,
+    /// Allocations that were trolled, but that their owners have not freed yet.
+    trolled: Table<SLOTS>,
+    /// Policy to troll by. Behind the same lock, since rolling the dice changes it.
+    troller: Troller,
+}
+
+/// Trolling wrapper around another allocator.
+///
+/// `SLOTS` is the number of allocations the side table can keep track of at once.
+pub struct Troll<A, const SLOTS: usize = DEFAULT_SLOTS> {
+    /// Allocator doing the actual work.
+    inner: A,
+    /// Held while anything is touching the side table.
+    lock: AtomicBool,
+    /// Live and trolled allocations, and how to troll them.
+    table: UnsafeCell<SideTable<SLOTS>>,
+}
+
+unsafe impl<A: Sync, const SLOTS: usize> Sync for Troll<A, SLOTS> {}
+unsafe impl<A: Send, const SLOTS: usize> Send for Troll<A, SLOTS> {}
+
+impl<A, const SLOTS: usize> Troll<A, SLOTS> {
+    /// Wrap an allocator.
+    pub const fn new(inner: A) -> Self {
+        Self::with_policy(inner, TrollPolicy::DEFAULT)
+    }
+
+    /// Wrap an allocator, trolling by `policy` instead of the [default](TrollPolicy::DEFAULT)
+    /// one, e.g. `static A: Troll<System> = Troll::with_policy(System, POLICY)`. A constructor
+    /// rather than a builder like [`Trollocator::with_policy`](crate::gjallocator::Trollocator::with_policy),
+    /// since a `const fn` cannot throw away the old side table without knowing how to drop an `A`.
+    pub const fn with_policy(inner: A, policy: TrollPolicy) -> Self {
+        Self {
+            inner,
+            lock: AtomicBool::new(false),
+            table: UnsafeCell::new(SideTable { live: Table::new(), trolled: Table::new(), troller: Troller::new(policy) }),
+        }
+    }
+
+    /// Troll by `policy` from now on, starting its random number generator over.
+    pub fn set_policy(&self, policy: TrollPolicy) {
+        self.with_table(|table| table.troller = Troller::new(policy));
+    }
+
+    /// Get the wrapped allocator.
+    pub fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Get the number of live allocations being tracked.
+    pub fn get_alloced_blocks(&self) -> usize {
+        self.with_table(|table| table.live.len)
+    }
+
+    /// Run `f` on the side table while holding the lock.
+    fn with_table<R>(&self, f: impl FnOnce(&mut SideTable<SLOTS>) -> R) -> R {
+        while self.lock.compare_exchange_weak(false, true, Ordering::Acquire, Ordering::Relaxed).is_err() {
+            hint::spin_loop();
+        }
+
+        // SAFETY: we hold the lock.
+        let result = f(unsafe { &mut *self.table.get() });
+        self.lock.store(false, Ordering::Release);
+        result
+    }
+}
+
+impl<A: GlobalAlloc, const SLOTS: usize> Troll<A, SLOTS> {
+    /// Remember a fresh allocation, then maybe troll one.
+    unsafe fn track(&self, ptr: *mut u8, layout: Layout) {
+        // Use ASLR as a seed for randomness, like the gjallocator does, unless the policy has a seed.
+        let _stack_marker: u8 = 0b01010101;
+        let entropy = (&_stack_marker as *const u8 as u64) ^ (ptr as u64);
+
+        let victim = self.with_table(|table| {
+            // The inner allocator reused the memory of a trolled allocation, so its owner is
+            // going to free this one instead. Nothing to remember for them anymore.
+            table.trolled.remove(ptr);
+
+            table.live.insert(Allocation { ptr, size: layout.size(), align: layout.align() });
+
+            // Without the `trolling` feature, this is just a very slow allocator. No room to
+            // remember a trolled allocation means its owner would free it twice.
+            if !cfg!(feature = "trolling") || table.trolled.len == SLOTS || !table.troller.policy.actions.contains(TrollAction::Free) {
+                return None;
+            }
+
+            let randex = table.troller.victim(entropy, table.live.len)?;
+            let victim = table.live.swap_remove(randex);
+            // Remembered as trolled before it is actually freed, so that its owner's free is
+            // swallowed even if it comes in first
+            table.trolled.insert(victim);
+            table.troller.log(TrollAction::Free, victim.ptr, victim.size, TrollDetail::None);
+            Some(victim)
+        });
+
+        if let Some(victim) = victim {
+            // Get owned.
+            self.inner.dealloc(victim.ptr, victim.layout());
+        }
+    }
+}
+
+unsafe impl<A: GlobalAlloc, const SLOTS: usize> GlobalAlloc for Troll<A, SLOTS> {
+    /// Allocate with the inner allocator, and maybe troll something.
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc(layout);
+        if !ptr.is_null() {
+            self.track(ptr, layout);
+        }
+        ptr
+    }
+
+    /// Allocate zeroed memory with the inner allocator, and maybe troll something.
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        let ptr = self.inner.alloc_zeroed(layout);
+        if !ptr.is_null() {
+            self.track(ptr, layout);
+        }
+        ptr
+    }
+
+    /// Free an allocation, unless it was already trolled.
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        let already_freed = self.with_table(|table| {
+            if table.trolled.remove(ptr).is_some() {
+                return true;
+            }
+
+            table.live.remove(ptr);
+            false
+        });
+
+        if !already_freed {
+            self.inner.dealloc(ptr, layout);
+        }
+    }
+
+    /// Reallocate with the inner allocator, keeping the side table up to date.
+    ///
+    /// Reallocating a trolled allocation gets a fresh one instead, with whatever the stale
+    /// memory holds copied into it.
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let new_layout = Layout::from_size_align_unchecked(new_size, layout.align());
+
+        // Out of the live set before the inner allocator gets to it, so that nobody else can pick
+        // it to troll while it is being moved
+        let (trolled, tracked) = self.with_table(|table| {
+            if table.trolled.remove(ptr).is_some() {
+                return (true, None);
+            }
+
+            (false, table.live.remove(ptr))
+        });
+
+        if trolled {
+            let new_ptr = self.alloc(new_layout);
+            if !new_ptr.is_null() {
+                core::ptr::copy_nonoverlapping(ptr, new_ptr, core::cmp::min(layout.size(), new_size));
+            }
+            return new_ptr;
+        }
+
+        let new_ptr = self.inner.realloc(ptr, layout, new_size);
+        self.with_table(|table| {
+            if new_ptr.is_null() {
+                // Still the old one, back in it goes
+                if let Some(allocation) = tracked {
+                    table.live.insert(allocation);
+                }
+                return;
+            }
+
+            // Same as in `track`, the new memory might have been trolled before
+            table.trolled.remove(new_ptr);
+
+            if tracked.is_some() {
+                table.live.insert(Allocation { ptr: new_ptr, size: new_size, align: layout.align() });
+            }
+        });
+        new_ptr
+    }
+}